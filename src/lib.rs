@@ -1,7 +1,333 @@
 pub mod version_utils {
+    use super::theme::parse_color;
+    use super::tooltip::TooltipFormat;
+    use super::updates::PackageUpdate;
     use alpm::vercmp;
-    use lenient_semver;
     use std::cmp::Ordering;
+    use std::fmt;
+    use std::str::FromStr;
+    use unicode_width::UnicodeWidthStr;
+
+    /// Kind of version change, ordered from most to least significant.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum UpdateKind {
+        Epoch,
+        Major,
+        Minor,
+        Patch,
+        PreRelease,
+        Devel,
+        Rebuild,
+        Other,
+    }
+
+    impl UpdateKind {
+        // Position in the --color-semver-updates list:
+        // Major, Minor, Patch, Pre, Other, Epoch, Rebuild, Devel
+        pub fn color_slot(self) -> usize {
+            match self {
+                UpdateKind::Major => 0,
+                UpdateKind::Minor => 1,
+                UpdateKind::Patch => 2,
+                UpdateKind::PreRelease => 3,
+                UpdateKind::Other => 4,
+                UpdateKind::Epoch => 5,
+                UpdateKind::Rebuild => 6,
+                UpdateKind::Devel => 7,
+            }
+        }
+    }
+
+    impl fmt::Display for UpdateKind {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let name = match self {
+                UpdateKind::Epoch => "epoch",
+                UpdateKind::Major => "major",
+                UpdateKind::Minor => "minor",
+                UpdateKind::Patch => "patch",
+                UpdateKind::PreRelease => "pre",
+                UpdateKind::Devel => "devel",
+                UpdateKind::Rebuild => "rebuild",
+                UpdateKind::Other => "other",
+            };
+            f.write_str(name)
+        }
+    }
+
+    impl FromStr for UpdateKind {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "epoch" => Ok(UpdateKind::Epoch),
+                "major" => Ok(UpdateKind::Major),
+                "minor" => Ok(UpdateKind::Minor),
+                "patch" => Ok(UpdateKind::Patch),
+                "pre" => Ok(UpdateKind::PreRelease),
+                "devel" => Ok(UpdateKind::Devel),
+                "rebuild" => Ok(UpdateKind::Rebuild),
+                "other" => Ok(UpdateKind::Other),
+                _ => Err(format!(
+                    "Invalid update kind '{}'. Expected one of: epoch, major, minor, patch, pre, devel, rebuild, other",
+                    s
+                )),
+            }
+        }
+    }
+
+    // Split a pacman version into (epoch, pkgver, pkgrel) the same way libalpm does
+    pub fn split_version(version: &str) -> (&str, &str, Option<&str>) {
+        let (epoch, rest) = match version.split_once(':') {
+            Some((epoch, rest)) if epoch.chars().all(|c| c.is_ascii_digit()) => {
+                (if epoch.is_empty() { "0" } else { epoch }, rest)
+            }
+            _ => ("0", version),
+        };
+        match rest.rsplit_once('-') {
+            Some((pkgver, pkgrel)) => (epoch, pkgver, Some(pkgrel)),
+            None => (epoch, rest, None),
+        }
+    }
+
+    // Classify an upgrade using libalpm version semantics. The numeric segments
+    // of pkgver before the first alphabetic one are read as major.minor.patch...,
+    // an unchanged pkgver with a new pkgrel is a rebuild, and VCS pkgvers
+    // (r123.abcdef, 1.2.r3.gabcdef0) are devel updates.
+    pub fn update_kind(old_version: &str, new_version: &str) -> UpdateKind {
+        if vercmp(new_version, old_version) != Ordering::Greater {
+            return UpdateKind::Other;
+        }
+        let (old_epoch, old_pkgver, _) = split_version(old_version);
+        let (new_epoch, new_pkgver, _) = split_version(new_version);
+
+        if vercmp(old_epoch, new_epoch) != Ordering::Equal {
+            return UpdateKind::Epoch;
+        }
+        if vercmp(old_pkgver, new_pkgver) == Ordering::Equal {
+            return UpdateKind::Rebuild;
+        }
+        if is_devel_version(new_pkgver) {
+            return UpdateKind::Devel;
+        }
+
+        let (old_release, old_suffix) = release_segments(old_pkgver);
+        let (new_release, new_suffix) = release_segments(new_pkgver);
+        let changed = (0..old_release.len().max(new_release.len())).find(|&index| {
+            old_release.get(index).copied().unwrap_or(0)
+                != new_release.get(index).copied().unwrap_or(0)
+        });
+        match changed {
+            Some(0) => UpdateKind::Major,
+            Some(1) => UpdateKind::Minor,
+            Some(_) => UpdateKind::Patch,
+            None if is_pre_release(&old_suffix) || is_pre_release(&new_suffix) => {
+                UpdateKind::PreRelease
+            }
+            None if old_suffix != new_suffix => UpdateKind::Patch,
+            None => UpdateKind::Other,
+        }
+    }
+
+    // Split pkgver into the alphanumeric runs libalpm compares (digits and letters
+    // separately) and return the leading numeric ones (the release) and the rest
+    fn release_segments(pkgver: &str) -> (Vec<u64>, Vec<&str>) {
+        let mut segments = Vec::new();
+        let mut start: Option<(usize, bool)> = None;
+        for (index, c) in pkgver.char_indices() {
+            if let Some((begin, digits)) = start {
+                if !c.is_ascii_alphanumeric() || c.is_ascii_digit() != digits {
+                    segments.push(&pkgver[begin..index]);
+                    start = None;
+                }
+            }
+            if start.is_none() && c.is_ascii_alphanumeric() {
+                start = Some((index, c.is_ascii_digit()));
+            }
+        }
+        if let Some((begin, _)) = start {
+            segments.push(&pkgver[begin..]);
+        }
+
+        let numeric = segments
+            .iter()
+            .take_while(|segment| segment.chars().all(|c| c.is_ascii_digit()))
+            .count();
+        let release = segments[..numeric]
+            .iter()
+            .map(|segment| segment.parse().unwrap_or(u64::MAX))
+            .collect();
+        (release, segments[numeric..].to_vec())
+    }
+
+    fn is_pre_release(suffix: &[&str]) -> bool {
+        suffix.first().is_some_and(|tag| {
+            ["alpha", "beta", "rc", "pre", "preview", "a", "b"]
+                .contains(&tag.to_ascii_lowercase().as_str())
+        })
+    }
+
+    // VCS packages use pkgvers like r1234.abcdef0 or 1.2.r3.gabcdef0: a revision
+    // count followed by an abbreviated commit hash (`r\d+\.g?[0-9a-f]{7,}`)
+    fn is_devel_version(pkgver: &str) -> bool {
+        let parts: Vec<&str> = pkgver.split(['.', '+', '_']).collect();
+        parts.windows(2).any(|pair| {
+            let revision = pair[0]
+                .strip_prefix('r')
+                .is_some_and(|rest| !rest.is_empty() && rest.chars().all(|c| c.is_ascii_digit()));
+            let hash = pair[1].strip_prefix('g').unwrap_or(pair[1]);
+            revision
+                && hash.len() >= 7
+                && hash
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+        })
+    }
+
+    // Name suffixes of VCS packages built from the latest upstream commit
+    pub fn is_devel_package(name: &str) -> bool {
+        ["-git", "-svn", "-hg", "-bzr"]
+            .iter()
+            .any(|suffix| name.ends_with(suffix))
+    }
+
+    /// Horizontal alignment of a tooltip column.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum Alignment {
+        #[default]
+        Left,
+        Right,
+    }
+
+    impl FromStr for Alignment {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "left" => Ok(Alignment::Left),
+                "right" => Ok(Alignment::Right),
+                _ => Err(format!("Invalid alignment '{}'. Expected left or right", s)),
+            }
+        }
+    }
+
+    /// Widths (in terminal columns) and alignments of the tooltip table columns.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct ColumnLayout {
+        pub widths: Vec<usize>,
+        pub alignments: Vec<Alignment>,
+    }
+
+    impl ColumnLayout {
+        // left aligned columns of the given widths
+        pub fn new(widths: impl Into<Vec<usize>>) -> Self {
+            let widths = widths.into();
+            ColumnLayout {
+                alignments: vec![Alignment::Left; widths.len()],
+                widths,
+            }
+        }
+
+        // widths that fit every cell of `rows`; columns without an alignment are left aligned
+        pub fn fit(rows: &[Vec<String>], alignments: &[Alignment]) -> Self {
+            let columns = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+            let widths = (0..columns)
+                .map(|column| {
+                    rows.iter()
+                        .filter_map(|row| row.get(column))
+                        .map(|cell| display_width(cell))
+                        .max()
+                        .unwrap_or(0)
+                })
+                .collect();
+            let alignments = (0..columns)
+                .map(|column| alignments.get(column).copied().unwrap_or_default())
+                .collect();
+            ColumnLayout { widths, alignments }
+        }
+
+        // pad a cell of the given column, never panics on cells wider than the column
+        pub fn pad(&self, column: usize, text: &str) -> String {
+            self.pad_with_markup(column, text, text)
+        }
+
+        // pad `markup` as if it were the plain `text` it renders
+        pub fn pad_with_markup(&self, column: usize, text: &str, markup: &str) -> String {
+            let width = self.widths.get(column).copied().unwrap_or(0);
+            let fill = " ".repeat(width.saturating_sub(display_width(text)));
+            match self.alignments.get(column).copied().unwrap_or_default() {
+                Alignment::Left => format!("{}{}", markup, fill),
+                Alignment::Right => format!("{}{}", fill, markup),
+            }
+        }
+    }
+
+    // Width of text on screen, so that arrows like `→` or CJK versions line up
+    pub fn display_width(text: &str) -> usize {
+        UnicodeWidthStr::width(text)
+    }
+
+    // Translate a comma separated style like "bold,underline,color=ff0000"
+    // into Pango span attributes
+    pub fn pango_attributes(style: &str) -> Result<String, String> {
+        style
+            .split(',')
+            .map(|attribute| match attribute.split_once('=') {
+                None if attribute == "bold" => Ok("weight='bold'".to_string()),
+                None if attribute == "italic" => Ok("style='italic'".to_string()),
+                None if attribute == "underline" => Ok("underline='single'".to_string()),
+                Some(("color", color)) => Ok(format!("color='#{}'", parse_color(color)?)),
+                Some(("background", color)) => {
+                    Ok(format!("background='#{}'", parse_color(color)?))
+                }
+                _ => Err(format!(
+                    "Invalid style attribute '{}'. Expected bold, italic, underline, color=<hex> or background=<hex>",
+                    attribute
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|attributes| attributes.join(" "))
+    }
+
+    // Wrap the part of `new_version` that differs from `old_version` in a span,
+    // starting at the beginning of the changed segment (1.4.6 -> 1.4.<7>, 2.10 -> 2.<11>)
+    pub fn highlight_version_change(
+        old_version: &str,
+        new_version: &str,
+        attributes: &str,
+    ) -> String {
+        let mut split = old_version
+            .char_indices()
+            .zip(new_version.chars())
+            .find(|((_, old), new)| old != new)
+            .map(|((index, _), _)| index)
+            .unwrap_or(old_version.len().min(new_version.len()));
+        if split >= new_version.len() {
+            return new_version.to_string();
+        }
+
+        // back up to the start of the digit or letter run we split in the middle of
+        let digits = new_version[split..].starts_with(|c: char| c.is_ascii_digit());
+        while let Some(previous) = new_version[..split].chars().next_back() {
+            if !previous.is_ascii_alphanumeric() || previous.is_ascii_digit() != digits {
+                break;
+            }
+            split -= previous.len_utf8();
+        }
+        // don't start the highlight with a separator
+        while let Some(next) = new_version[split..].chars().next() {
+            if next.is_ascii_alphanumeric() || split + next.len_utf8() >= new_version.len() {
+                break;
+            }
+            split += next.len_utf8();
+        }
+
+        format!(
+            "{}<span {}>{}</span>",
+            &new_version[..split],
+            attributes,
+            &new_version[split..]
+        )
+    }
 
     // Helper function to compare versions using ALPM's vercmp for production consistency
     pub fn is_version_newer(aur_version: &str, local_version: &str) -> bool {
@@ -10,113 +336,2149 @@ pub mod version_utils {
         matches!(vercmp(aur_version, local_version), Ordering::Greater)
     }
 
+    // The string based highlighters below are kept for existing callers and
+    // format "name old -> new" lines through TooltipFormat.
     pub fn highlight_semantic_version(
         packages: String,
-        colors: [&str; 5],
+        colors: [&str; 8],
         override_colors: bool,
         overrides: [&str; 4],
-        padding: Option<[usize; 4]>,
+        padding: Option<ColumnLayout>,
+    ) -> String {
+        let format = TooltipFormat {
+            kind_colors: Some(colors),
+            column_colors: override_colors.then_some(overrides),
+            ..Default::default()
+        };
+        format_lines(&packages, format, padding.as_ref())
+    }
+
+    pub fn override_columns(
+        text: String,
+        overrides: [&str; 4],
+        padding: Option<ColumnLayout>,
+    ) -> String {
+        let format = TooltipFormat {
+            column_colors: Some(overrides),
+            ..Default::default()
+        };
+        format_lines(&text, format, padding.as_ref())
+    }
+
+    pub fn override_columns_from_packages(
+        packages: String,
+        overrides: [&str; 4],
+        padding: Option<ColumnLayout>,
+    ) -> String {
+        override_columns(packages, overrides, padding)
+    }
+
+    fn format_lines(
+        packages: &str,
+        format: TooltipFormat,
+        layout: Option<&ColumnLayout>,
     ) -> String {
         packages
             .lines()
-            .map(|package| {
-                let fragments = package.split_whitespace().collect::<Vec<_>>();
-                let mut text = package.to_string();
-
-                if override_colors {
-                    text = override_columns(text, overrides, padding);
-                } else if let Some(padding) = padding {
-                    text = fragments
+            .map(
+                |line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+                    [name, old_version, arrow, new_version] => {
+                        let update = PackageUpdate::new(name, old_version, new_version);
+                        TooltipFormat {
+                            arrow,
+                            ..format.clone()
+                        }
+                        .format_updates(&[&update], layout)
+                    }
+                    // unknown format, so only columns are padded and coloured
+                    ref fragments => fragments
                         .iter()
                         .enumerate()
-                        .map(|(index, word)| {
-                            word.to_string() + " ".repeat(padding[index % 4] - word.len()).as_str()
+                        .map(|(column, fragment)| {
+                            let text = match layout {
+                                Some(layout) => layout.pad(column, fragment),
+                                None => fragment.to_string(),
+                            };
+                            match format
+                                .column_colors
+                                .and_then(|colors| colors.get(column).copied())
+                            {
+                                Some(color) if !color.is_empty() => {
+                                    format!("<span color='#{}'>{}</span>", color, text)
+                                }
+                                _ => text,
+                            }
                         })
                         .collect::<Vec<_>>()
-                        .join(" ");
+                        .join(" "),
+                },
+            )
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+pub mod updates {
+    use super::security::SecurityFix;
+    use super::version_utils::{is_devel_package, is_version_newer, update_kind, UpdateKind};
+    use serde::{Deserialize, Serialize};
+    use std::cmp::Reverse;
+    use std::fmt;
+    use std::str::FromStr;
+
+    /// A single pending upgrade, as reported by `checkupdates` or the AUR.
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct PackageUpdate {
+        pub name: String,
+        pub old_version: String,
+        pub new_version: String,
+        // set when pacman marks the package with `[ignored]` (IgnorePkg/IgnoreGroup)
+        pub ignored: bool,
+        // sync repository providing the new version, or "aur"
+        pub repository: Option<String>,
+        // bytes left to download, 0 when the package is already in the cache (repo packages only)
+        pub download_size: Option<i64>,
+        // installed size of the new version minus the installed one, in bytes
+        pub installed_size_delta: Option<i64>,
+        // build date of the new version (AUR: last modification), unix time
+        pub build_date: Option<i64>,
+        // AUR package base and its installed packages when the update stands for a
+        // split package, `name` is then one of them
+        #[serde(default)]
+        pub base: Option<String>,
+        #[serde(default)]
+        pub split_packages: Vec<String>,
+        // package base of a repo update in the sync database (repo packages only)
+        #[serde(default)]
+        pub pkgbase: Option<String>,
+        // vulnerabilities fixed by this update, from the Arch security tracker
+        #[serde(default)]
+        pub security: Option<SecurityFix>,
+    }
+
+    impl PackageUpdate {
+        pub fn new(name: &str, old_version: &str, new_version: &str) -> Self {
+            PackageUpdate {
+                name: name.to_string(),
+                old_version: old_version.to_string(),
+                new_version: new_version.to_string(),
+                ignored: false,
+                repository: None,
+                download_size: None,
+                installed_size_delta: None,
+                build_date: None,
+                base: None,
+                split_packages: Vec::new(),
+                pkgbase: None,
+                security: None,
+            }
+        }
+
+        pub fn kind(&self) -> UpdateKind {
+            match update_kind(&self.old_version, &self.new_version) {
+                UpdateKind::Major
+                | UpdateKind::Minor
+                | UpdateKind::Patch
+                | UpdateKind::PreRelease
+                | UpdateKind::Other
+                    if is_devel_package(&self.name) =>
+                {
+                    UpdateKind::Devel
                 }
+                kind => kind,
+            }
+        }
 
-                if fragments.len() != 4 {
-                    // unknown format, so we skip formatting
-                    return text;
+        // name shown in the tooltip, e.g. `foo (foo-cli, foo-libs)` for a split package
+        pub fn display_name(&self) -> String {
+            match &self.base {
+                Some(base) => format!("{} ({})", base, self.split_packages.join(", ")),
+                None => self.name.clone(),
+            }
+        }
+
+        // installed packages upgraded by this update
+        pub fn packages(&self) -> Vec<&str> {
+            if self.split_packages.is_empty() {
+                vec![self.name.as_str()]
+            } else {
+                self.split_packages.iter().map(String::as_str).collect()
+            }
+        }
+    }
+
+    // Merge updates of installed split packages into one entry per package base,
+    // since AUR helpers build all of them together. `updates` pairs each update
+    // with its package base. Members only merge when they upgrade between the same
+    // versions, and the entry keeps the name of the member named like the base, or
+    // else the first one.
+    pub fn group_split_packages(updates: Vec<(String, PackageUpdate)>) -> Vec<PackageUpdate> {
+        let mut grouped: Vec<PackageUpdate> = Vec::new();
+        for (base, mut update) in updates {
+            let entry = grouped.iter_mut().find(|entry| {
+                entry.base.as_ref() == Some(&base)
+                    && entry.old_version == update.old_version
+                    && entry.new_version == update.new_version
+            });
+            match entry {
+                Some(entry) => {
+                    if update.name == base {
+                        entry.name = update.name.clone();
+                    }
+                    entry.split_packages.push(update.name);
+                }
+                None => {
+                    update.split_packages = vec![update.name.clone()];
+                    update.base = Some(base);
+                    grouped.push(update);
+                }
+            }
+        }
+        grouped
+            .into_iter()
+            .map(|mut update| {
+                update.split_packages.sort();
+                // a single member named like its base isn't a split package
+                if update.split_packages.len() == 1 && update.base.as_ref() == Some(&update.name) {
+                    update.base = None;
+                    update.split_packages.clear();
                 }
+                update
+            })
+            .collect()
+    }
 
-                let (Ok(old_version), Ok(new_version)) = (
-                    lenient_semver::parse(fragments[1]),
-                    lenient_semver::parse(fragments[3]),
-                ) else {
-                    return text;
-                };
+    // Drop updates installed since they were found, e.g. cached AUR updates after
+    // an upgrade. `installed` gives the local version of a package, and an update
+    // stays while any of its packages is installed at an older version.
+    pub fn drop_installed(
+        updates: &mut Vec<PackageUpdate>,
+        installed: impl Fn(&str) -> Option<String>,
+    ) {
+        updates.retain(|update| {
+            update.packages().into_iter().any(|name| {
+                installed(name)
+                    .is_some_and(|version| is_version_newer(&update.new_version, &version))
+            })
+        });
+    }
+
+    impl fmt::Display for PackageUpdate {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(
+                f,
+                "{} {} -> {}",
+                self.name, self.old_version, self.new_version
+            )
+        }
+    }
+
+    // Parse `checkupdates` (or `pacman -Qu`) output into update records.
+    // Lines that don't look like `name old -> new` (warnings, errors, blank lines)
+    // are skipped, and anything after the new version is ignored except `[ignored]`.
+    pub fn parse_updates(output: &str) -> Vec<PackageUpdate> {
+        output.lines().filter_map(parse_update_line).collect()
+    }
+
+    fn parse_update_line(line: &str) -> Option<PackageUpdate> {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        if fields.len() < 4 || fields[2] != "->" || !is_package_name(fields[0]) {
+            return None;
+        }
+        let mut update = PackageUpdate::new(fields[0], fields[1], fields[3]);
+        update.ignored = fields[4..].contains(&"[ignored]");
+        Some(update)
+    }
+
+    // Group updates by repository. Groups follow the order of `repositories`
+    // (pacman.conf order), anything else (AUR, unresolved) comes after in the order seen.
+    pub fn group_by_repository<'a>(
+        updates: &'a [PackageUpdate],
+        repositories: &[String],
+    ) -> Vec<(String, Vec<&'a PackageUpdate>)> {
+        let mut groups: Vec<(String, Vec<&PackageUpdate>)> = Vec::new();
+        for update in updates {
+            let repository = update.repository.as_deref().unwrap_or("unknown");
+            match groups.iter_mut().find(|(name, _)| name == repository) {
+                Some((_, members)) => members.push(update),
+                None => groups.push((repository.to_string(), vec![update])),
+            }
+        }
+        groups.sort_by_key(|(name, _)| {
+            repositories
+                .iter()
+                .position(|repository| repository == name)
+                .unwrap_or(repositories.len())
+        });
+        groups
+    }
+
+    /// Tooltip ordering selected with `--sort`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum SortKey {
+        Name,
+        Kind,
+        Size,
+        Repository,
+        Date,
+    }
+
+    impl FromStr for SortKey {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "name" => Ok(SortKey::Name),
+                "kind" => Ok(SortKey::Kind),
+                "size" => Ok(SortKey::Size),
+                "repo" => Ok(SortKey::Repository),
+                "date" => Ok(SortKey::Date),
+                _ => Err(format!(
+                    "Invalid sort key '{}'. Expected one of: name, kind, size, repo, date",
+                    s
+                )),
+            }
+        }
+    }
 
-                let color = {
-                    if new_version.major > old_version.major {
-                        colors[0]
-                    } else if new_version.minor > old_version.minor {
-                        colors[1]
-                    } else if new_version.patch > old_version.patch {
-                        colors[2]
-                    } else if new_version.pre > old_version.pre {
-                        colors[3]
+    /// What to do with pkgrel-only rebuilds, selected with `--rebuilds`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum RebuildMode {
+        Show,
+        Hide,
+        Collapse,
+    }
+
+    impl FromStr for RebuildMode {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "show" => Ok(RebuildMode::Show),
+                "hide" => Ok(RebuildMode::Hide),
+                "collapse" => Ok(RebuildMode::Collapse),
+                _ => Err(format!(
+                    "Invalid rebuilds mode '{}'. Expected one of: show, hide, collapse",
+                    s
+                )),
+            }
+        }
+    }
+
+    // Move pkgrel-only rebuilds out of `updates`, keeping the order of both lists
+    pub fn take_rebuilds(updates: &mut Vec<PackageUpdate>) -> Vec<PackageUpdate> {
+        let (rebuilds, rest) = updates
+            .drain(..)
+            .partition(|update| update.kind() == UpdateKind::Rebuild);
+        *updates = rest;
+        rebuilds
+    }
+
+    // Sort updates in place. Kind puts major updates first, size and date put the
+    // largest/newest first, repo follows `repositories` (pacman.conf order).
+    // Entries missing the sort field go last, ties are broken by name.
+    pub fn sort_updates(updates: &mut [PackageUpdate], key: SortKey, repositories: &[String]) {
+        updates.sort_by(|a, b| a.name.cmp(&b.name));
+        match key {
+            SortKey::Name => {}
+            SortKey::Kind => updates.sort_by_key(|update| update.kind()),
+            SortKey::Size => updates.sort_by_key(|update| Reverse(update.download_size)),
+            SortKey::Date => updates.sort_by_key(|update| Reverse(update.build_date)),
+            SortKey::Repository => updates.sort_by_key(|update| {
+                update
+                    .repository
+                    .as_ref()
+                    .and_then(|name| {
+                        repositories
+                            .iter()
+                            .position(|repository| repository == name)
+                    })
+                    .unwrap_or(repositories.len())
+            }),
+        }
+    }
+
+    // Summary line for entries left out of a truncated tooltip,
+    // e.g. "…and 250 more (40 major)"
+    pub fn truncation_summary(hidden: &[PackageUpdate]) -> String {
+        let major = hidden
+            .iter()
+            .filter(|update| matches!(update.kind(), UpdateKind::Epoch | UpdateKind::Major))
+            .count();
+        if major > 0 {
+            format!("…and {} more ({} major)", hidden.len(), major)
+        } else {
+            format!("…and {} more", hidden.len())
+        }
+    }
+
+    // Total (download size, installed size change) over updates with known sizes
+    pub fn total_sizes(updates: &[PackageUpdate]) -> (i64, i64) {
+        updates
+            .iter()
+            .fold((0, 0), |(download, installed), update| {
+                (
+                    download + update.download_size.unwrap_or(0),
+                    installed + update.installed_size_delta.unwrap_or(0),
+                )
+            })
+    }
+
+    // Human readable size using binary units like pacman, e.g. "12.34 MiB"
+    pub fn format_size(bytes: i64) -> String {
+        const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+        let mut size = bytes.unsigned_abs() as f64;
+        let mut unit = 0;
+        while size >= 1024.0 && unit < UNITS.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+        let sign = if bytes < 0 { "-" } else { "" };
+        if unit == 0 {
+            format!("{}{} {}", sign, size, UNITS[unit])
+        } else {
+            format!("{}{:.2} {}", sign, size, UNITS[unit])
+        }
+    }
+
+    // Like format_size, but always signed, e.g. "+1.50 KiB"
+    pub fn format_size_delta(bytes: i64) -> String {
+        if bytes > 0 {
+            format!("+{}", format_size(bytes))
+        } else {
+            format_size(bytes)
+        }
+    }
+
+    // Expand {count}, {download_size} and {installed_delta} in a --text-format string
+    pub fn fill_text_format(format: &str, count: usize, sizes: (i64, i64)) -> String {
+        format
+            .replace("{count}", &count.to_string())
+            .replace("{download_size}", &format_size(sizes.0))
+            .replace("{installed_delta}", &format_size_delta(sizes.1))
+    }
+
+    // pacman package names only contain alphanumerics and `@._+-`,
+    // which rules out prefixes like `warning:` or `==>`
+    fn is_package_name(name: &str) -> bool {
+        !name.starts_with('-')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "@._+-".contains(c))
+    }
+}
+
+pub mod theme {
+    use super::version_utils::UpdateKind;
+    use std::collections::HashMap;
+
+    /// Colours of the update kinds when no theme or colour list is given,
+    /// in UpdateKind::color_slot order.
+    pub const DEFAULT_KIND_COLORS: [&str; 8] = [
+        "ff0000", "00ff00", "0000ff", "ff00ff", "ffffff", "ff8800", "808080", "00ffff",
+    ];
+
+    const NAMED_COLORS: [(&str, &str); 20] = [
+        ("black", "000000"),
+        ("white", "ffffff"),
+        ("red", "ff0000"),
+        ("green", "008000"),
+        ("lime", "00ff00"),
+        ("blue", "0000ff"),
+        ("yellow", "ffff00"),
+        ("cyan", "00ffff"),
+        ("aqua", "00ffff"),
+        ("magenta", "ff00ff"),
+        ("fuchsia", "ff00ff"),
+        ("orange", "ffa500"),
+        ("purple", "800080"),
+        ("pink", "ffc0cb"),
+        ("brown", "a52a2a"),
+        ("gray", "808080"),
+        ("grey", "808080"),
+        ("silver", "c0c0c0"),
+        ("navy", "000080"),
+        ("teal", "008080"),
+    ];
+
+    /// Colours and styles of the tooltip, from a built-in palette or a theme file.
+    #[derive(Debug, Clone, PartialEq, Eq, Default)]
+    pub struct Theme {
+        // in UpdateKind::color_slot order, None leaves lines uncoloured
+        pub kind_colors: Option<[String; 8]>,
+        // name, old version, arrow and new version columns, "" for none
+        pub column_colors: [String; 4],
+        // Pango attributes of AUR and repository lines
+        pub aur_style: Option<String>,
+        pub repo_style: Option<String>,
+    }
+
+    impl Theme {
+        pub fn builtin(name: &str) -> Option<Theme> {
+            let kind_colors = match name {
+                "default" => DEFAULT_KIND_COLORS,
+                "gruvbox" => [
+                    "fb4934", "b8bb26", "83a598", "d3869b", "ebdbb2", "fe8019", "928374", "8ec07c",
+                ],
+                "nord" => [
+                    "bf616a", "a3be8c", "81a1c1", "b48ead", "eceff4", "d08770", "4c566a", "88c0d0",
+                ],
+                "catppuccin" => [
+                    "f38ba8", "a6e3a1", "89b4fa", "cba6f7", "cdd6f4", "fab387", "6c7086", "94e2d5",
+                ],
+                _ => return None,
+            };
+            Some(Theme {
+                kind_colors: Some(kind_colors.map(String::from)),
+                ..Default::default()
+            })
+        }
+
+        // Parse a GTK/waybar style sheet: `@define-color name value;` followed by
+        // rules like `.major { color: @red; }` or `.aur { font-style: italic; }`
+        pub fn parse(css: &str) -> Result<Theme, String> {
+            let css = strip_comments(css);
+            let mut theme = Theme::default();
+            let mut defines: HashMap<&str, String> = HashMap::new();
+            let mut rest = css.as_str();
+
+            loop {
+                rest = rest.trim_start();
+                if rest.is_empty() {
+                    return Ok(theme);
+                }
+                if let Some(definition) = rest.strip_prefix("@define-color") {
+                    let Some((statement, after)) = definition.split_once(';') else {
+                        return Err("Missing ';' after @define-color".to_string());
+                    };
+                    let Some((name, value)) = statement.trim().split_once(char::is_whitespace)
+                    else {
+                        return Err(format!("Invalid @define-color '{}'", statement.trim()));
+                    };
+                    let color = resolve_color(value.trim(), &defines)?;
+                    defines.insert(name, color);
+                    rest = after;
+                } else {
+                    let Some((selectors, after)) = rest.split_once('{') else {
+                        return Err(format!("Expected a rule, found '{}'", rest));
+                    };
+                    let Some((body, after)) = after.split_once('}') else {
+                        return Err(format!("Missing '}}' after '{}'", selectors.trim()));
+                    };
+                    let declarations = body
+                        .split(';')
+                        .filter(|declaration| !declaration.trim().is_empty())
+                        .map(|declaration| match declaration.split_once(':') {
+                            Some((property, value)) => Ok((property.trim(), value.trim())),
+                            None => Err(format!("Invalid declaration '{}'", declaration.trim())),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    for selector in selectors.split(',') {
+                        theme.apply_rule(selector.trim(), &declarations, &defines)?;
+                    }
+                    rest = after;
+                }
+            }
+        }
+
+        fn apply_rule(
+            &mut self,
+            selector: &str,
+            declarations: &[(&str, &str)],
+            defines: &HashMap<&str, String>,
+        ) -> Result<(), String> {
+            let Some(class) = selector.strip_prefix('.') else {
+                return Err(format!(
+                    "Invalid selector '{}'. Expected a class like .major or .aur",
+                    selector
+                ));
+            };
+            match class {
+                "aur" => self.aur_style = Some(style_attributes(declarations, defines)?),
+                "repo" => self.repo_style = Some(style_attributes(declarations, defines)?),
+                _ => {
+                    let color = match declarations {
+                        [("color", value)] => resolve_color(value, defines)?,
+                        _ => return Err(format!("Only 'color' can be set for '{}'", selector)),
+                    };
+                    let column = ["name", "old-version", "arrow", "new-version"]
+                        .iter()
+                        .position(|column| *column == class);
+                    if let Some(column) = column {
+                        self.column_colors[column] = color;
                     } else {
-                        colors[4]
+                        let kind: UpdateKind = class.parse()?;
+                        self.kind_colors
+                            .get_or_insert_with(|| DEFAULT_KIND_COLORS.map(String::from))
+                            [kind.color_slot()] = color;
                     }
-                };
+                }
+            }
+            Ok(())
+        }
+    }
+
+    // Normalise #rgb, #rgba, #rrggbb, #rrggbbaa (the '#' is optional) and
+    // named colours to lowercase hex without the '#'
+    pub fn parse_color(color: &str) -> Result<String, String> {
+        let hex = color.strip_prefix('#').unwrap_or(color);
+        if hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            match hex.len() {
+                3 | 4 => {
+                    return Ok(hex
+                        .chars()
+                        .flat_map(|c| [c, c])
+                        .collect::<String>()
+                        .to_lowercase())
+                }
+                6 | 8 => return Ok(hex.to_lowercase()),
+                _ => {}
+            }
+        }
+        NAMED_COLORS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(color))
+            .map(|(_, hex)| hex.to_string())
+            .ok_or(format!(
+                "Invalid color '{}'. Expected #rgb, #rrggbb, #rrggbbaa or a color name",
+                color
+            ))
+    }
 
-                format!("<span color='#{}'>{}</span>", color, text)
+    fn resolve_color(value: &str, defines: &HashMap<&str, String>) -> Result<String, String> {
+        match value.strip_prefix('@') {
+            Some(name) => defines
+                .get(name)
+                .cloned()
+                .ok_or(format!("Undefined color '@{}'", name)),
+            None => parse_color(value),
+        }
+    }
 
+    // CSS declarations to Pango span attributes
+    fn style_attributes(
+        declarations: &[(&str, &str)],
+        defines: &HashMap<&str, String>,
+    ) -> Result<String, String> {
+        declarations
+            .iter()
+            .map(|(property, value)| match (*property, *value) {
+                ("color", color) => Ok(format!("color='#{}'", resolve_color(color, defines)?)),
+                ("background-color", color) => {
+                    Ok(format!("background='#{}'", resolve_color(color, defines)?))
+                }
+                ("font-weight", weight @ ("normal" | "bold")) => Ok(format!("weight='{}'", weight)),
+                ("font-style", style @ ("normal" | "italic" | "oblique")) => {
+                    Ok(format!("style='{}'", style))
+                }
+                ("text-decoration", "underline") => Ok("underline='single'".to_string()),
+                ("text-decoration", "line-through") => Ok("strikethrough='true'".to_string()),
+                ("text-decoration", "none") => Ok("underline='none'".to_string()),
+                _ => Err(format!("Unsupported declaration '{}: {}'", property, value)),
             })
-            .collect::<Vec<_>>()
-            .join("\n")
+            .collect::<Result<Vec<_>, _>>()
+            .map(|attributes| attributes.join(" "))
     }
 
-    pub fn override_columns(
-        text: String,
-        overrides: [&str; 4],
-        padding: Option<[usize; 4]>,
-    ) -> String {
-        text.split_whitespace()
-            .enumerate()
-            .map(|(element_index, element)| {
-                // Apply padding if specified
-                let padded_element = if let Some(padding) = padding {
-                    format!(
-                        "{}{}",
-                        element,
-                        " ".repeat(padding[element_index % 4] - element.len())
-                    )
-                } else {
-                    element.to_string()
+    fn strip_comments(css: &str) -> String {
+        let mut stripped = String::new();
+        let mut rest = css;
+        while let Some((before, comment)) = rest.split_once("/*") {
+            stripped.push_str(before);
+            rest = comment.split_once("*/").map_or("", |(_, after)| after);
+        }
+        stripped.push_str(rest);
+        stripped
+    }
+}
+
+pub mod tooltip {
+    use super::updates::{format_size, PackageUpdate};
+    use super::version_utils::{highlight_version_change, ColumnLayout};
+    use std::str::FromStr;
+
+    /// A column of the tooltip table. Name, old version, arrow and new version
+    /// are always shown, the others can be added with `--tooltip-columns`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Column {
+        Name,
+        OldVersion,
+        Arrow,
+        NewVersion,
+        Repository,
+        Size,
+        Kind,
+    }
+
+    impl FromStr for Column {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "repo" => Ok(Column::Repository),
+                "size" => Ok(Column::Size),
+                "kind" => Ok(Column::Kind),
+                _ => Err(format!(
+                    "Invalid tooltip column '{}'. Expected one of: repo, size, kind",
+                    s
+                )),
+            }
+        }
+    }
+
+    /// How update records are turned into tooltip lines.
+    #[derive(Debug, Clone)]
+    pub struct TooltipFormat<'a> {
+        pub arrow: &'a str,
+        pub extra_columns: Vec<Column>,
+        // colour whole lines by update kind, in UpdateKind::color_slot order
+        pub kind_colors: Option<[&'a str; 8]>,
+        // colours of the name, old version, arrow and new version columns, "" for none
+        pub column_colors: Option<[&'a str; 4]>,
+        // Pango attributes for the changed part of the new version
+        pub version_change_attributes: Option<String>,
+        // Pango attributes of whole AUR and repository lines
+        pub aur_style: Option<String>,
+        pub repo_style: Option<String>,
+    }
+
+    impl Default for TooltipFormat<'_> {
+        fn default() -> Self {
+            TooltipFormat {
+                arrow: "->",
+                extra_columns: Vec::new(),
+                kind_colors: None,
+                column_colors: None,
+                version_change_attributes: None,
+                aur_style: None,
+                repo_style: None,
+            }
+        }
+    }
+
+    impl TooltipFormat<'_> {
+        pub fn columns(&self) -> Vec<Column> {
+            let mut columns = vec![
+                Column::Name,
+                Column::OldVersion,
+                Column::Arrow,
+                Column::NewVersion,
+            ];
+            columns.extend(&self.extra_columns);
+            columns
+        }
+
+        // plain text of each column for one update
+        pub fn cells(&self, update: &PackageUpdate) -> Vec<String> {
+            self.columns()
+                .into_iter()
+                .map(|column| match column {
+                    Column::Name => update.display_name(),
+                    Column::OldVersion => update.old_version.clone(),
+                    Column::Arrow => self.arrow.to_string(),
+                    Column::NewVersion => update.new_version.clone(),
+                    Column::Repository => update.repository.clone().unwrap_or("-".to_string()),
+                    Column::Size => update
+                        .download_size
+                        .map(format_size)
+                        .unwrap_or("-".to_string()),
+                    Column::Kind => update.kind().to_string(),
+                })
+                .collect()
+        }
+
+        // one tooltip line per update, padded to `layout` when aligning columns
+        pub fn format_updates(
+            &self,
+            updates: &[&PackageUpdate],
+            layout: Option<&ColumnLayout>,
+        ) -> String {
+            updates
+                .iter()
+                .map(|update| self.format_update(update, layout))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+
+        fn format_update(&self, update: &PackageUpdate, layout: Option<&ColumnLayout>) -> String {
+            let columns = self.columns();
+            let line = self
+                .cells(update)
+                .iter()
+                .enumerate()
+                .map(|(column, cell)| {
+                    let markup = match (&self.version_change_attributes, columns[column]) {
+                        (Some(attributes), Column::NewVersion) => {
+                            highlight_version_change(&update.old_version, cell, attributes)
+                        }
+                        _ => cell.clone(),
+                    };
+                    let text = match layout {
+                        Some(layout) => layout.pad_with_markup(column, cell, &markup),
+                        None => markup,
+                    };
+                    match self
+                        .column_colors
+                        .and_then(|colors| colors.get(column).copied())
+                    {
+                        Some(color) if !color.is_empty() => {
+                            format!("<span color='#{}'>{}</span>", color, text)
+                        }
+                        _ => text,
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            let line = match self.kind_colors {
+                Some(colors) => format!(
+                    "<span color='#{}'>{}</span>",
+                    colors[update.kind().color_slot()],
+                    line
+                ),
+                None => line,
+            };
+            let source_style = match update.repository.as_deref() {
+                Some("aur") => &self.aur_style,
+                _ => &self.repo_style,
+            };
+            let line = match source_style {
+                Some(style) => format!("<span {}>{}</span>", style, line),
+                None => line,
+            };
+            match &update.security {
+                Some(fix) => format!("{} <b>[{}]</b>", line, fix),
+                None => line,
+            }
+        }
+    }
+}
+
+pub mod output {
+    use super::updates::PackageUpdate;
+    use serde_json::json;
+    use std::str::FromStr;
+
+    /// Bar protocol the module state is printed in.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum OutputFormat {
+        #[default]
+        Waybar,
+        I3blocks,
+        I3bar,
+        Polybar,
+        Yambar,
+        Plain,
+        Json,
+    }
+
+    impl FromStr for OutputFormat {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "waybar" => Ok(OutputFormat::Waybar),
+                "i3blocks" => Ok(OutputFormat::I3blocks),
+                "i3bar" => Ok(OutputFormat::I3bar),
+                "polybar" => Ok(OutputFormat::Polybar),
+                "yambar" => Ok(OutputFormat::Yambar),
+                "plain" => Ok(OutputFormat::Plain),
+                "json" => Ok(OutputFormat::Json),
+                _ => Err(format!(
+                    "Invalid output format '{}'. Expected one of: waybar, i3blocks, i3bar, polybar, yambar, plain, json",
+                    s
+                )),
+            }
+        }
+    }
+
+    /// What the module shows at one point in time, independent of the bar.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct ModuleState {
+        pub text: String,
+        // Pango markup
+        pub tooltip: String,
+        // "has-updates" or "updated"
+        pub class: &'static str,
+        // some update fixes a vulnerability, adds the "security" class
+        pub security: bool,
+        pub updates: Vec<PackageUpdate>,
+    }
+
+    impl ModuleState {
+        // waybar accepts a list of classes
+        pub fn classes(&self) -> serde_json::Value {
+            if self.security {
+                json!([self.class, "security"])
+            } else {
+                json!(self.class)
+            }
+        }
+    }
+
+    impl OutputFormat {
+        // printed once before the first state
+        pub fn header(self) -> Option<&'static str> {
+            match self {
+                OutputFormat::I3bar => Some("{\"version\":1}\n["),
+                _ => None,
+            }
+        }
+
+        pub fn render(self, state: &ModuleState) -> String {
+            match self {
+                OutputFormat::Waybar => json!({
+                    "text": state.text,
+                    "tooltip": state.tooltip,
+                    "class": state.classes(),
+                    "alt": state.class,
+                })
+                .to_string(),
+                // i3blocks with `interval=persist` and `markup=pango`
+                OutputFormat::I3blocks => single_line(&state.text),
+                OutputFormat::I3bar => format!(
+                    "[{}],",
+                    json!({
+                        "name": "pacman-updates",
+                        "full_text": state.text,
+                        "markup": "pango",
+                    })
+                ),
+                // polybar custom/script with `tail = true`
+                OutputFormat::Polybar => pango_to_polybar(&single_line(&state.text)),
+                // yambar script module, a transaction ends with an empty line
+                OutputFormat::Yambar => format!(
+                    "count|int|{}\ntext|string|{}\nupdated|bool|{}\n",
+                    state.updates.len(),
+                    single_line(&strip_markup(&state.text)),
+                    state.class == "updated"
+                ),
+                OutputFormat::Plain => single_line(&strip_markup(&state.text)),
+                // for eww's deflisten and other scripts
+                OutputFormat::Json => {
+                    let updates = state
+                        .updates
+                        .iter()
+                        .map(|update| {
+                            let mut value = json!(update);
+                            value["kind"] = json!(update.kind().to_string());
+                            value
+                        })
+                        .collect::<Vec<_>>();
+                    json!({
+                        "count": state.updates.len(),
+                        "text": state.text,
+                        "tooltip": state.tooltip,
+                        "class": state.class,
+                        "security": state.security,
+                        "updates": updates,
+                    })
+                    .to_string()
+                }
+            }
+        }
+    }
+
+    fn single_line(text: &str) -> String {
+        text.replace('\n', " ")
+    }
+
+    // Drop Pango tags and resolve the entities Pango needs escaped
+    pub fn strip_markup(markup: &str) -> String {
+        let mut text = String::new();
+        let mut rest = markup;
+        while let Some(start) = rest.find('<') {
+            text.push_str(&rest[..start]);
+            rest = rest[start..].split_once('>').map_or("", |(_, after)| after);
+        }
+        text.push_str(rest);
+        unescape(&text)
+    }
+
+    // Translate Pango colours and underlines to polybar formatting tags,
+    // restoring the enclosing colour when a span closes
+    pub fn pango_to_polybar(markup: &str) -> String {
+        let mut output = String::new();
+        let mut foregrounds: Vec<String> = Vec::new();
+        let mut backgrounds: Vec<String> = Vec::new();
+        // (sets foreground, sets background, underlines) of each open tag
+        let mut open: Vec<(bool, bool, bool)> = Vec::new();
+        let mut rest = markup;
+
+        while let Some(start) = rest.find('<') {
+            output.push_str(&unescape(&rest[..start]).replace('%', "%%"));
+            let Some((tag, after)) = rest[start + 1..].split_once('>') else {
+                rest = "";
+                break;
+            };
+            rest = after;
+
+            if tag.starts_with('/') {
+                let Some((foreground, background, underline)) = open.pop() else {
+                    continue;
                 };
+                if foreground {
+                    foregrounds.pop();
+                    output.push_str(&restore('F', foregrounds.last()));
+                }
+                if background {
+                    backgrounds.pop();
+                    output.push_str(&restore('B', backgrounds.last()));
+                }
+                if underline {
+                    output.push_str("%{-u}");
+                }
+                continue;
+            }
 
-                // Apply color override if specified
-                if !overrides[element_index].is_empty() {
-                    format!(
-                        "<span color='#{}'>{}</span>",
-                        overrides[element_index], padded_element
-                    )
+            let (name, attributes) = tag.split_once(' ').unwrap_or((tag, ""));
+            let mut opened = (false, false, name == "u");
+            for (attribute, value) in tag_attributes(attributes) {
+                match attribute {
+                    "color" | "foreground" | "fgcolor" => {
+                        output.push_str(&format!("%{{F{}}}", value));
+                        foregrounds.push(value.to_string());
+                        opened.0 = true;
+                    }
+                    "background" | "bgcolor" => {
+                        output.push_str(&format!("%{{B{}}}", value));
+                        backgrounds.push(value.to_string());
+                        opened.1 = true;
+                    }
+                    "underline" if value != "none" => opened.2 = true,
+                    _ => {}
+                }
+            }
+            if opened.2 {
+                output.push_str("%{+u}");
+            }
+            open.push(opened);
+        }
+        output.push_str(&unescape(rest).replace('%', "%%"));
+        output
+    }
+
+    fn restore(kind: char, enclosing: Option<&String>) -> String {
+        match enclosing {
+            Some(color) => format!("%{{{}{}}}", kind, color),
+            None => format!("%{{{}-}}", kind),
+        }
+    }
+
+    // name='value' pairs of a tag, values may contain spaces
+    fn tag_attributes(mut attributes: &str) -> Vec<(&str, &str)> {
+        let mut pairs = Vec::new();
+        while let Some((name, after)) = attributes.split_once('=') {
+            let after = after.trim_start();
+            let Some(quote) = after.chars().next().filter(|c| *c == '\'' || *c == '"') else {
+                break;
+            };
+            let Some((value, after)) = after[1..].split_once(quote) else {
+                break;
+            };
+            pairs.push((name.trim(), value));
+            attributes = after;
+        }
+        pairs
+    }
+
+    fn unescape(text: &str) -> String {
+        text.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&")
+    }
+}
+
+pub mod details {
+    use super::updates::PackageUpdate;
+    use serde::{Deserialize, Serialize};
+
+    pub const DEFAULT_GITLAB_URL: &str = "https://gitlab.archlinux.org";
+
+    /// A packaging commit, as returned by the GitLab compare API.
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Commit {
+        pub short_id: String,
+        pub title: String,
+        pub author_name: String,
+        // ISO 8601
+        pub created_at: String,
+    }
+
+    #[derive(Deserialize)]
+    struct Comparison {
+        commits: Vec<Commit>,
+    }
+
+    // Project of a package base, named the way Arch's devtools do: `+` between
+    // words becomes `-` (dvd+rw-tools is dvd-rw-tools), any other `+` is `plus`
+    // (libsigc++ is libsigcplusplus), other unsupported characters and runs of
+    // separators become `-`, and `tree` is `unix-tree`
+    pub fn gitlab_project(pkgbase: &str) -> String {
+        let chars = pkgbase.chars().collect::<Vec<_>>();
+        let mut name = String::new();
+        let mut i = 0;
+        while i < chars.len() {
+            // s/([a-zA-Z0-9]+)\+([a-zA-Z]+)/\1-\2/g
+            let words = chars[i..]
+                .iter()
+                .take_while(|c| c.is_ascii_alphanumeric())
+                .count();
+            let letters = chars[i + words..]
+                .iter()
+                .skip(1)
+                .take_while(|c| c.is_ascii_alphabetic())
+                .count();
+            if words > 0 && chars.get(i + words) == Some(&'+') && letters > 0 {
+                name.extend(&chars[i..i + words]);
+                name.push('-');
+                name.extend(&chars[i + words + 1..i + words + 1 + letters]);
+                i += words + 1 + letters;
+            } else {
+                name.push(chars[i]);
+                i += 1;
+            }
+        }
+        let name = name
+            .replace('+', "plus")
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || "_-.".contains(c) {
+                    c
                 } else {
-                    padded_element
+                    '-'
                 }
             })
-            .collect::<Vec<_>>()
-            .join(" ")
+            .collect::<String>();
+        // s/[_\-]{2,}/-/g
+        let mut path = String::new();
+        for c in name.chars() {
+            if (c == '_' || c == '-') && path.ends_with(['_', '-']) {
+                path.pop();
+                path.push('-');
+            } else {
+                path.push(c);
+            }
+        }
+        if path == "tree" {
+            path = "unix-tree".to_string();
+        }
+        format!("archlinux/packaging/packages/{}", path)
     }
 
-    pub fn override_columns_from_packages(
-        packages: String,
-        overrides: [&str; 4],
-        padding: Option<[usize; 4]>,
+    // File name of a cached comparison. Tags are only unique per GitLab instance,
+    // so its host is part of the name.
+    pub fn details_cache_name(gitlab_url: &str, pkgbase: &str, update: &PackageUpdate) -> String {
+        let host = gitlab_url
+            .split_once("://")
+            .map_or(gitlab_url, |(_, rest)| rest)
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .replace(
+                |c: char| !c.is_ascii_alphanumeric() && c != '.' && c != '-',
+                "_",
+            );
+        format!(
+            "{}_{}_{}_{}.json",
+            host,
+            gitlab_project(pkgbase).replace('/', "_"),
+            version_tag(&update.old_version),
+            version_tag(&update.new_version)
+        )
+    }
+
+    // release tags replace the epoch colon, e.g. `1:2.0-1` is tagged `1-2.0-1`
+    pub fn version_tag(version: &str) -> String {
+        version.replace(':', "-")
+    }
+
+    // Packaging commits between the installed and the new version
+    pub fn compare_url(gitlab_url: &str, pkgbase: &str, update: &PackageUpdate) -> String {
+        format!(
+            "{}/api/v4/projects/{}/repository/compare?from={}&to={}",
+            gitlab_url.trim_end_matches('/'),
+            encode(&gitlab_project(pkgbase)),
+            encode(&version_tag(&update.old_version)),
+            encode(&version_tag(&update.new_version))
+        )
+    }
+
+    // Page of the same comparison, for the user to open
+    pub fn compare_page(gitlab_url: &str, pkgbase: &str, update: &PackageUpdate) -> String {
+        format!(
+            "{}/{}/-/compare/{}...{}",
+            gitlab_url.trim_end_matches('/'),
+            gitlab_project(pkgbase),
+            version_tag(&update.old_version),
+            version_tag(&update.new_version)
+        )
+    }
+
+    pub fn parse_commits(json: &str) -> Result<Vec<Commit>, String> {
+        serde_json::from_str::<Comparison>(json)
+            .map(|comparison| comparison.commits)
+            .map_err(|err| format!("Invalid GitLab compare response: {}", err))
+    }
+
+    // percent-encode everything but unreserved characters
+    fn encode(component: &str) -> String {
+        component
+            .bytes()
+            .map(|byte| match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    (byte as char).to_string()
+                }
+                _ => format!("%{:02X}", byte),
+            })
+            .collect()
+    }
+
+    // Text printed by the `details` command, newest commit first
+    pub fn format_details(
+        update: &PackageUpdate,
+        upstream: Option<&str>,
+        page: &str,
+        commits: &[Commit],
     ) -> String {
-        packages
+        let mut lines = vec![update.to_string()];
+        if let Some(upstream) = upstream {
+            lines.push(format!("Upstream: {}", upstream));
+        }
+        lines.push(format!("Packaging: {}", page));
+        for commit in commits.iter().rev() {
+            let date = commit.created_at.get(..10).unwrap_or(&commit.created_at);
+            lines.push(format!(
+                "  {} {} ({}, {})",
+                commit.short_id, commit.title, commit.author_name, date
+            ));
+        }
+        if commits.is_empty() {
+            lines.push("  no packaging commits".to_string());
+        }
+        lines.join("\n")
+    }
+}
+
+pub mod history {
+    use super::updates::PackageUpdate;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum HistoryEvent {
+        // the update became pending
+        Available,
+        // the package has no pending update anymore, usually because it was upgraded
+        Applied,
+    }
+
+    /// One line of the JSONL history.
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct HistoryEntry {
+        // unix time
+        pub time: u64,
+        pub event: HistoryEvent,
+        pub name: String,
+        pub old_version: String,
+        pub new_version: String,
+        pub repository: Option<String>,
+    }
+
+    impl HistoryEntry {
+        fn new(time: u64, event: HistoryEvent, update: &PackageUpdate) -> Self {
+            HistoryEntry {
+                time,
+                event,
+                name: update.name.clone(),
+                old_version: update.old_version.clone(),
+                new_version: update.new_version.clone(),
+                repository: update.repository.clone(),
+            }
+        }
+    }
+
+    /// A package upgrade recorded in pacman.log.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct LoggedUpgrade {
+        // unix time
+        pub time: i64,
+        // as written in the log, e.g. `2024-05-30T10:01:02+0200`
+        pub timestamp: String,
+        pub name: String,
+        pub old_version: String,
+        pub new_version: String,
+    }
+
+    // Transitions from the previously recorded pending updates to the current ones.
+    // A newer version of a pending update is a new available update, not an applied one.
+    pub fn history_events(
+        previous: &[PackageUpdate],
+        current: &[PackageUpdate],
+        now: u64,
+    ) -> Vec<HistoryEntry> {
+        let appeared = current.iter().filter(|update| {
+            !previous
+                .iter()
+                .any(|known| known.name == update.name && known.new_version == update.new_version)
+        });
+        let applied = previous
+            .iter()
+            .filter(|update| !current.iter().any(|pending| pending.name == update.name));
+        appeared
+            .map(|update| HistoryEntry::new(now, HistoryEvent::Available, update))
+            .chain(applied.map(|update| HistoryEntry::new(now, HistoryEvent::Applied, update)))
+            .collect()
+    }
+
+    // Pending updates to record for an instance seeing `current`. Without `aur`
+    // (--no-aur) it can't tell whether AUR updates were applied, so the recorded
+    // ones are kept for the instances that check the AUR.
+    pub fn recorded_updates(
+        previous: &[PackageUpdate],
+        current: &[PackageUpdate],
+        aur: bool,
+    ) -> Vec<PackageUpdate> {
+        let mut recorded = current.to_vec();
+        if !aur {
+            recorded.extend(
+                previous
+                    .iter()
+                    .filter(|update| update.repository.as_deref() == Some("aur"))
+                    .filter(|update| !current.iter().any(|pending| pending.name == update.name))
+                    .cloned(),
+            );
+        }
+        recorded
+    }
+
+    // Entries of the JSONL history, skipping lines that can't be parsed
+    pub fn parse_history(jsonl: &str) -> Vec<HistoryEntry> {
+        jsonl
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect()
+    }
+
+    // Upgrades in pacman.log, e.g.
+    // `[2024-05-30T10:01:02+0200] [ALPM] upgraded linux (6.9.1-1 -> 6.9.2-1)`
+    pub fn parse_pacman_log(log: &str) -> Vec<LoggedUpgrade> {
+        log.lines()
+            .filter_map(|line| {
+                let (timestamp, rest) = line.strip_prefix('[')?.split_once("] [ALPM] upgraded ")?;
+                let (name, versions) = rest.split_once(" (")?;
+                let (old_version, new_version) = versions.strip_suffix(')')?.split_once(" -> ")?;
+                Some(LoggedUpgrade {
+                    time: parse_log_time(timestamp)?,
+                    timestamp: timestamp.to_string(),
+                    name: name.to_string(),
+                    old_version: old_version.to_string(),
+                    new_version: new_version.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    // Unix time of a pacman.log timestamp like `2024-05-30T10:01:02+0200`. Logs
+    // written before pacman 5.2 have no time zone and are skipped.
+    pub fn parse_log_time(timestamp: &str) -> Option<i64> {
+        let field = |range: std::ops::Range<usize>| timestamp.get(range)?.parse::<i64>().ok();
+        if timestamp.len() != 24 {
+            return None;
+        }
+        let (year, month, day) = (field(0..4)?, field(5..7)?, field(8..10)?);
+        let (hour, minute, second) = (field(11..13)?, field(14..16)?, field(17..19)?);
+        let offset = (field(20..22)? * 60 + field(22..24)?) * 60;
+        let offset = match &timestamp[19..20] {
+            "+" => offset,
+            "-" => -offset,
+            _ => return None,
+        };
+        // days since 1970-01-01 in the proleptic Gregorian calendar
+        let (y, m) = if month <= 2 {
+            (year - 1, month + 9)
+        } else {
+            (year, month - 3)
+        };
+        let era = y.div_euclid(400);
+        let year_of_era = y - era * 400;
+        let day_of_year = (153 * m + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146097 + day_of_era - 719468;
+        Some(days * 86400 + hour * 3600 + minute * 60 + second - offset)
+    }
+
+    // Each available update with the logged upgrade that installed it (the first upgrade
+    // to that version afterwards) and the seconds in between
+    pub fn install_lags<'a>(
+        history: &'a [HistoryEntry],
+        upgrades: &'a [LoggedUpgrade],
+    ) -> Vec<(&'a HistoryEntry, &'a LoggedUpgrade, i64)> {
+        history
+            .iter()
+            .filter(|entry| entry.event == HistoryEvent::Available)
+            .filter_map(|entry| {
+                let upgrade = upgrades.iter().find(|upgrade| {
+                    upgrade.name == entry.name
+                        && upgrade.new_version == entry.new_version
+                        && upgrade.time >= entry.time as i64
+                })?;
+                Some((entry, upgrade, upgrade.time - entry.time as i64))
+            })
+            .collect()
+    }
+
+    // e.g. "2d 4h", "3h 12m" or "45s"
+    pub fn format_duration(seconds: i64) -> String {
+        let (days, hours) = (seconds / 86400, seconds % 86400 / 3600);
+        let (minutes, seconds) = (seconds % 3600 / 60, seconds % 60);
+        if days > 0 {
+            format!("{}d {}h", days, hours)
+        } else if hours > 0 {
+            format!("{}h {}m", hours, minutes)
+        } else if minutes > 0 {
+            format!("{}m", minutes)
+        } else {
+            format!("{}s", seconds)
+        }
+    }
+}
+
+pub mod hook {
+    use std::path::Path;
+
+    pub const DEFAULT_HOOK_PATH: &str = "/etc/pacman.d/hooks/waybar-module-pacman-updates.hook";
+
+    // ALPM hook that runs `<executable> notify` after every transaction
+    pub fn alpm_hook(executable: &str) -> String {
+        format!(
+            "[Trigger]
+Operation = Install
+Operation = Upgrade
+Operation = Remove
+Type = Package
+Target = *
+
+[Action]
+Description = Refreshing waybar-module-pacman-updates...
+When = PostTransaction
+Exec = {} notify
+",
+            executable
+        )
+    }
+
+    // Whether a NUL separated /proc/<pid>/cmdline belongs to a module instance
+    // that listens for refresh signals, as opposed to a one-shot or subcommand run
+    pub fn is_module_instance(cmdline: &str) -> bool {
+        let mut args = cmdline.split('\0').filter(|arg| !arg.is_empty());
+        let Some(program) = args.next() else {
+            return false;
+        };
+        if Path::new(program).file_name() != Some("waybar-module-pacman-updates".as_ref()) {
+            return false;
+        }
+        let args = args.collect::<Vec<_>>();
+        !args
+            .iter()
+            .any(|arg| ["--once", "--list", "--help"].contains(arg))
+            && args
+                .first()
+                .is_none_or(|arg| arg.starts_with('-') || *arg == "daemon")
+    }
+
+    // Whether the SigCgt mask of a /proc/<pid>/status file shows a handler for
+    // `signal`, which instances only install once they are ready to refresh
+    pub fn catches_signal(status: &str, signal: i32) -> bool {
+        status
             .lines()
-            .map(|package| {
-                let text = package.to_string();
-                override_columns(text, overrides, padding)
+            .find_map(|line| line.strip_prefix("SigCgt:"))
+            .and_then(|mask| u64::from_str_radix(mask.trim(), 16).ok())
+            .is_some_and(|mask| (1..=64).contains(&signal) && mask & (1 << (signal - 1)) != 0)
+    }
+}
+
+pub mod daemon {
+    use super::cache::fallback_dir;
+    use super::updates::PackageUpdate;
+    use std::path::PathBuf;
+
+    // $XDG_RUNTIME_DIR/waybar-module-pacman-updates.sock, or a socket in the user's
+    // private directory in /tmp
+    pub fn socket_path(runtime_dir: Option<&str>, uid: u32) -> PathBuf {
+        match runtime_dir.filter(|dir| dir.starts_with('/')) {
+            Some(dir) => PathBuf::from(dir).join("waybar-module-pacman-updates.sock"),
+            None => fallback_dir(uid).join("daemon.sock"),
+        }
+    }
+
+    // The daemon sends every update list as one JSON line
+    pub fn encode_updates(updates: &[PackageUpdate]) -> String {
+        serde_json::to_string(updates).expect("package updates are always serializable")
+    }
+
+    pub fn decode_updates(line: &str) -> Result<Vec<PackageUpdate>, String> {
+        serde_json::from_str(line)
+            .map_err(|err| format!("Invalid update list from daemon: {}", err))
+    }
+}
+
+pub mod cache {
+    use super::notify::NotifierState;
+    use super::updates::PackageUpdate;
+    use serde::{Deserialize, Serialize};
+    use std::path::PathBuf;
+
+    /// Network results shared by all instances of a user through the cache file.
+    #[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(default)]
+    pub struct SharedCache {
+        // unix times of the last repository sync and AUR query
+        pub repo_synced: Option<u64>,
+        pub aur_synced: Option<u64>,
+        pub aur_updates: Vec<PackageUpdate>,
+        pub notifications: NotifierState,
+    }
+
+    impl SharedCache {
+        // an empty, corrupt or incompatible cache file is treated as empty
+        pub fn parse(contents: &str) -> SharedCache {
+            serde_json::from_str(contents).unwrap_or_default()
+        }
+
+        pub fn to_json(&self) -> String {
+            serde_json::to_string(self).expect("the cache is always serializable")
+        }
+    }
+
+    // Whether a sync at `synced` is less than `max_age` seconds old at `now`
+    pub fn is_fresh(synced: Option<u64>, now: u64, max_age: u64) -> bool {
+        synced.is_some_and(|synced| now.saturating_sub(synced) < max_age)
+    }
+
+    // Like is_fresh, but a sync from before `requested`, when a refresh was asked
+    // for, is stale however recent it is
+    pub fn is_fresh_since(synced: Option<u64>, now: u64, max_age: u64, requested: u64) -> bool {
+        is_fresh(synced, now, max_age) && synced.is_some_and(|synced| synced >= requested)
+    }
+
+    // Directory of this module under an XDG base directory, e.g.
+    // $XDG_CACHE_HOME/waybar-module-pacman-updates or ~/.cache/waybar-module-pacman-updates
+    pub fn app_dir(xdg_dir: Option<&str>, home: Option<&str>, fallback: &str, uid: u32) -> PathBuf {
+        // the spec says relative paths are invalid and must be ignored
+        match (
+            xdg_dir.filter(|dir| dir.starts_with('/')),
+            home.filter(|home| !home.is_empty()),
+        ) {
+            (Some(dir), _) => PathBuf::from(dir).join("waybar-module-pacman-updates"),
+            (None, Some(home)) => PathBuf::from(home)
+                .join(fallback)
+                .join("waybar-module-pacman-updates"),
+            (None, None) => fallback_dir(uid),
+        }
+    }
+
+    // Private directory in /tmp for users without $HOME or $XDG_RUNTIME_DIR, it
+    // has to be created with mode 0700 and checked before use
+    pub fn fallback_dir(uid: u32) -> PathBuf {
+        PathBuf::from(format!("/tmp/waybar-module-pacman-updates-{}", uid))
+    }
+}
+
+pub mod aur {
+    use std::str::FromStr;
+
+    /// Where AUR updates come from, selected with `--aur-backend`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum AurBackend {
+        // the AUR RPC interface, compared to `pacman -Qm`
+        #[default]
+        Api,
+        Paru,
+        Yay,
+    }
+
+    impl FromStr for AurBackend {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "api" => Ok(AurBackend::Api),
+                "paru" => Ok(AurBackend::Paru),
+                "yay" => Ok(AurBackend::Yay),
+                _ => Err(format!(
+                    "Invalid AUR backend '{}'. Expected one of: api, paru, yay",
+                    s
+                )),
+            }
+        }
+    }
+
+    impl AurBackend {
+        pub fn name(self) -> &'static str {
+            match self {
+                AurBackend::Api => "api",
+                AurBackend::Paru => "paru",
+                AurBackend::Yay => "yay",
+            }
+        }
+
+        // Helper command listing AUR updates (devel packages included) in
+        // checkupdates format, None for the built-in API backend
+        pub fn helper_command(self) -> Option<[&'static str; 5]> {
+            match self {
+                AurBackend::Api => None,
+                AurBackend::Paru | AurBackend::Yay => {
+                    Some([self.name(), "-Qua", "--devel", "--color", "never"])
+                }
+            }
+        }
+    }
+}
+
+pub mod security {
+    use super::updates::PackageUpdate;
+    use alpm::vercmp;
+    use serde::{Deserialize, Serialize};
+    use std::cmp::Ordering;
+    use std::fmt;
+
+    pub const DEFAULT_SECURITY_URL: &str = "https://security.archlinux.org/issues/all.json";
+
+    /// Severity of an advisory group, as rated by the Arch security team.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+    pub enum Severity {
+        Unknown,
+        Low,
+        Medium,
+        High,
+        Critical,
+    }
+
+    impl fmt::Display for Severity {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            fmt::Debug::fmt(self, f)
+        }
+    }
+
+    /// One advisory group (AVG) of the Arch security tracker.
+    #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+    pub struct Advisory {
+        pub name: String,
+        pub packages: Vec<String>,
+        pub severity: Severity,
+        // first vulnerable version
+        pub affected: String,
+        // first version with the fix, None while the packages are still vulnerable
+        pub fixed: Option<String>,
+        // CVE identifiers
+        pub issues: Vec<String>,
+    }
+
+    /// Vulnerabilities a pending update fixes.
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct SecurityFix {
+        // highest severity of the fixed advisories
+        pub severity: Severity,
+        pub issues: Vec<String>,
+    }
+
+    impl fmt::Display for SecurityFix {
+        // e.g. `High: CVE-2024-1234 +2`
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{}", self.severity)?;
+            if let Some(first) = self.issues.first() {
+                write!(f, ": {}", first)?;
+            }
+            if self.issues.len() > 1 {
+                write!(f, " +{}", self.issues.len() - 1)?;
+            }
+            Ok(())
+        }
+    }
+
+    // Parse the tracker's `issues/all.json`, keeping only advisories that have a fix
+    pub fn parse_advisories(json: &str) -> Result<Vec<Advisory>, String> {
+        let mut advisories: Vec<Advisory> = serde_json::from_str(json)
+            .map_err(|err| format!("Invalid security tracker data: {}", err))?;
+        advisories.retain(|advisory| advisory.fixed.is_some());
+        Ok(advisories)
+    }
+
+    // Advisories fixed by upgrading from the installed to the new version: the
+    // installed version is affected and older than the fix, the new one isn't.
+    // Advisories may list the package or its `pkgbase`.
+    pub fn security_fix(update: &PackageUpdate, advisories: &[Advisory]) -> Option<SecurityFix> {
+        let mut fix: Option<SecurityFix> = None;
+        for advisory in advisories {
+            let Some(fixed) = advisory.fixed.as_deref() else {
+                continue;
+            };
+            let listed = advisory
+                .packages
+                .iter()
+                .any(|package| *package == update.name || Some(package) == update.pkgbase.as_ref());
+            if !listed
+                || vercmp(update.old_version.as_str(), advisory.affected.as_str()) == Ordering::Less
+                || vercmp(update.old_version.as_str(), fixed) != Ordering::Less
+                || vercmp(update.new_version.as_str(), fixed) == Ordering::Less
+            {
+                continue;
+            }
+            let fix = fix.get_or_insert(SecurityFix {
+                severity: advisory.severity,
+                issues: Vec::new(),
+            });
+            fix.severity = fix.severity.max(advisory.severity);
+            for issue in &advisory.issues {
+                if !fix.issues.contains(issue) {
+                    fix.issues.push(issue.clone());
+                }
+            }
+        }
+        fix
+    }
+}
+
+pub mod upgrade {
+    use std::path::Path;
+
+    // tried in order when $TERMINAL isn't set
+    pub const TERMINALS: [&str; 10] = [
+        "xdg-terminal-exec",
+        "foot",
+        "kitty",
+        "alacritty",
+        "wezterm",
+        "ghostty",
+        "gnome-terminal",
+        "konsole",
+        "xfce4-terminal",
+        "xterm",
+    ];
+    pub const AUR_HELPERS: [&str; 3] = ["paru", "yay", "pikaur"];
+
+    // Command line running `command` in `terminal`, which may carry its own
+    // arguments like "foot -a updates". Unknown terminals get `-e`.
+    pub fn terminal_command(terminal: &str, command: &[String]) -> Vec<String> {
+        let mut argv = terminal
+            .split_whitespace()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        let program = argv
+            .first()
+            .and_then(|program| Path::new(program).file_name())
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        let separator: &[&str] = match program {
+            "xdg-terminal-exec" | "foot" | "kitty" => &[],
+            "wezterm" => &["start", "--"],
+            "gnome-terminal" => &["--"],
+            "xfce4-terminal" => &["-x"],
+            _ => &["-e"],
+        };
+        argv.extend(separator.iter().map(|arg| arg.to_string()));
+        argv.extend(command.iter().cloned());
+        argv
+    }
+
+    // Quote an argument for sh, leaving plain words like `paru` or `-Syu` as they are
+    pub fn shell_quote(arg: &str) -> String {
+        let plain = !arg.is_empty()
+            && arg
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_./=:@+,%".contains(c));
+        if plain {
+            arg.to_string()
+        } else {
+            format!("'{}'", arg.replace('\'', "'\\''"))
+        }
+    }
+
+    // Shell script for the terminal: upgrade, make running instances re-render from
+    // inside it (many terminals return before the script ends), then wait for Enter
+    // to keep the result readable
+    pub fn upgrade_script(upgrade: &[String], executable: &str) -> String {
+        let upgrade = upgrade
+            .iter()
+            .map(|arg| shell_quote(arg))
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!(
+            "{}; {} notify; echo; printf 'Press Enter to close'; read -r _",
+            upgrade,
+            shell_quote(executable)
+        )
+    }
+
+    // Upgrade everything with the AUR helper, or only the repositories with pacman
+    pub fn upgrade_command(helper: Option<&str>) -> Vec<String> {
+        match helper {
+            Some(helper) => vec![helper.to_string(), "-Syu".to_string()],
+            None => vec!["sudo".to_string(), "pacman".to_string(), "-Syu".to_string()],
+        }
+    }
+}
+
+pub mod notify {
+    use super::updates::{group_by_repository, PackageUpdate};
+    use serde::{Deserialize, Serialize};
+
+    // packages whose updates are worth a critical notification by default
+    pub const DEFAULT_CRITICAL_PACKAGES: [&str; 10] = [
+        "linux",
+        "linux-lts",
+        "linux-zen",
+        "linux-hardened",
+        "glibc",
+        "systemd",
+        "openssl",
+        "openssh",
+        "sudo",
+        "pacman",
+    ];
+
+    /// Urgency levels of the freedesktop notification spec.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Urgency {
+        Low = 0,
+        Normal = 1,
+        Critical = 2,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Notification {
+        pub summary: String,
+        pub body: String,
+        pub urgency: Urgency,
+    }
+
+    // Updates of `current` that weren't pending before, or are pending with a newer version now
+    pub fn new_updates<'a>(
+        previous: &[PackageUpdate],
+        current: &'a [PackageUpdate],
+    ) -> Vec<&'a PackageUpdate> {
+        current
+            .iter()
+            .filter(|update| {
+                !previous.iter().any(|known| {
+                    known.name == update.name && known.new_version == update.new_version
+                })
+            })
+            .collect()
+    }
+
+    // whether the update upgrades one of the `critical` packages, split ones included
+    fn is_critical(update: &PackageUpdate, critical: &[String]) -> bool {
+        update
+            .packages()
+            .iter()
+            .any(|package| critical.iter().any(|name| name == package))
+    }
+
+    // One notification for a batch of new updates, listing the names per repository
+    pub fn notification(
+        updates: &[PackageUpdate],
+        repositories: &[String],
+        critical: &[String],
+    ) -> Notification {
+        let summary = match updates.len() {
+            1 => "1 new update".to_string(),
+            count => format!("{} new updates", count),
+        };
+        let body = group_by_repository(updates, repositories)
+            .into_iter()
+            .map(|(repository, members)| {
+                let mut names = members
+                    .iter()
+                    .take(5)
+                    .map(|update| update.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                if members.len() > 5 {
+                    names = format!("{} and {} more", names, members.len() - 5);
+                }
+                format!("{}: {}", repository, names)
             })
             .collect::<Vec<_>>()
-            .join("\n")
+            .join("\n");
+        let urgency = if updates.iter().any(|update| is_critical(update, critical)) {
+            Urgency::Critical
+        } else {
+            Urgency::Normal
+        };
+        Notification {
+            summary,
+            body,
+            urgency,
+        }
+    }
+
+    /// What has been notified so far. It is kept in the shared cache, so every
+    /// update is notified once however many instances see it.
+    #[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(default)]
+    pub struct NotifierState {
+        // None until the first update set
+        pub previous: Option<Vec<PackageUpdate>>,
+        pub unnotified: Vec<PackageUpdate>,
+        pub last_sent: Option<u64>,
+    }
+
+    /// Decides when to notify about new updates. Updates that show up while
+    /// rate limited are collected into the next notification, critical ones
+    /// are notified right away.
+    pub struct Notifier {
+        min_interval: u64,
+        critical: Vec<String>,
+        // false with --no-aur, pending AUR updates are then left to other instances
+        aur: bool,
+    }
+
+    impl Notifier {
+        pub fn new(min_interval: u64, critical: Vec<String>, aur: bool) -> Self {
+            Notifier {
+                min_interval,
+                critical,
+                aur,
+            }
+        }
+
+        pub fn update(
+            &self,
+            state: &mut NotifierState,
+            current: &[PackageUpdate],
+            repositories: &[String],
+            now: u64,
+        ) -> Option<Notification> {
+            let unchecked =
+                |update: &PackageUpdate| !self.aur && update.repository.as_deref() == Some("aur");
+            let pending = |update: &PackageUpdate| {
+                unchecked(update)
+                    || current.iter().any(|pending| {
+                        pending.name == update.name && pending.new_version == update.new_version
+                    })
+            };
+            let mut known = current.to_vec();
+            if let Some(previous) = &state.previous {
+                known.extend(previous.iter().filter(|update| unchecked(update)).cloned());
+            }
+            // the first update set is only a baseline
+            let previous = state.previous.replace(known)?;
+            let new = new_updates(&previous, current);
+            // drop what was upgraded meanwhile, then take the latest version of the rest
+            state
+                .unnotified
+                .retain(|update| pending(update) && !new.iter().any(|new| new.name == update.name));
+            state.unnotified.extend(new.into_iter().cloned());
+            if state.unnotified.is_empty() {
+                return None;
+            }
+
+            let critical = state
+                .unnotified
+                .iter()
+                .any(|update| is_critical(update, &self.critical));
+            let limited = state
+                .last_sent
+                .is_some_and(|sent| now.saturating_sub(sent) < self.min_interval);
+            if limited && !critical {
+                return None;
+            }
+            state.last_sent = Some(now);
+            let updates = std::mem::take(&mut state.unnotified);
+            Some(notification(&updates, repositories, &self.critical))
+        }
+    }
+}
+
+#[cfg(feature = "dbus")]
+pub mod dbus {
+    use super::notify::Notification;
+    use super::updates::PackageUpdate;
+    use std::collections::HashMap;
+    use zbus::blocking::connection::Builder;
+    use zbus::blocking::Connection;
+    use zbus::interface;
+    use zbus::object_server::SignalEmitter;
+    use zbus::zvariant::Value;
+
+    pub const BUS_NAME: &str = "org.waybar.PacmanUpdates";
+    pub const OBJECT_PATH: &str = "/org/waybar/PacmanUpdates";
+
+    /// The `org.waybar.PacmanUpdates` interface: the pending updates as properties,
+    /// `Refresh()` to sync now and `Changed` whenever the update set changes.
+    pub struct UpdatesService {
+        updates: Vec<PackageUpdate>,
+        refresh: Box<dyn Fn() + Send + Sync>,
+    }
+
+    impl UpdatesService {
+        pub fn new(refresh: impl Fn() + Send + Sync + 'static) -> Self {
+            UpdatesService {
+                updates: Vec::new(),
+                refresh: Box::new(refresh),
+            }
+        }
+
+        // replace the published updates, false when they are the same as before
+        pub fn set_updates(&mut self, updates: &[PackageUpdate]) -> bool {
+            if self.updates.as_slice() == updates {
+                return false;
+            }
+            self.updates = updates.to_vec();
+            true
+        }
+
+        fn count_where(&self, aur: bool) -> u32 {
+            self.updates
+                .iter()
+                .filter(|update| (update.repository.as_deref() == Some("aur")) == aur)
+                .count() as u32
+        }
+    }
+
+    #[interface(name = "org.waybar.PacmanUpdates")]
+    impl UpdatesService {
+        #[zbus(property)]
+        pub fn count(&self) -> u32 {
+            self.updates.len() as u32
+        }
+
+        #[zbus(property)]
+        pub fn repo_count(&self) -> u32 {
+            self.count_where(false)
+        }
+
+        #[zbus(property)]
+        pub fn aur_count(&self) -> u32 {
+            self.count_where(true)
+        }
+
+        // (name, old version, new version, repository, kind)
+        #[zbus(property)]
+        pub fn updates(&self) -> Vec<(String, String, String, String, String)> {
+            self.updates
+                .iter()
+                .map(|update| {
+                    (
+                        update.name.clone(),
+                        update.old_version.clone(),
+                        update.new_version.clone(),
+                        update.repository.clone().unwrap_or_default(),
+                        update.kind().to_string(),
+                    )
+                })
+                .collect()
+        }
+
+        // sync the databases and the AUR now
+        pub fn refresh(&self) {
+            (self.refresh)();
+        }
+
+        #[zbus(signal)]
+        pub async fn changed(emitter: &SignalEmitter<'_>, count: u32) -> zbus::Result<()>;
+    }
+
+    // Show a notification through org.freedesktop.Notifications, returns its id
+    pub fn send_notification(
+        connection: &Connection,
+        notification: &Notification,
+    ) -> zbus::Result<u32> {
+        let hints = HashMap::from([("urgency", Value::U8(notification.urgency as u8))]);
+        let reply = connection.call_method(
+            Some("org.freedesktop.Notifications"),
+            "/org/freedesktop/Notifications",
+            Some("org.freedesktop.Notifications"),
+            "Notify",
+            &(
+                "waybar-module-pacman-updates",
+                0u32,
+                "system-software-update",
+                notification.summary.as_str(),
+                notification.body.as_str(),
+                Vec::<&str>::new(),
+                hints,
+                -1i32,
+            ),
+        )?;
+        reply.body().deserialize()
+    }
+
+    /// Owns the session bus name and publishes update sets on it.
+    pub struct DbusServer {
+        connection: Connection,
+    }
+
+    impl DbusServer {
+        pub fn start(service: UpdatesService) -> zbus::Result<DbusServer> {
+            let connection = Builder::session()?
+                .name(BUS_NAME)?
+                .serve_at(OBJECT_PATH, service)?
+                .build()?;
+            Ok(DbusServer { connection })
+        }
+
+        // update the properties and emit Changed, unless the set is the same as before
+        pub fn publish(&self, updates: &[PackageUpdate]) -> zbus::Result<()> {
+            let iface = self
+                .connection
+                .object_server()
+                .interface::<_, UpdatesService>(OBJECT_PATH)?;
+            let mut service = iface.get_mut();
+            if !service.set_updates(updates) {
+                return Ok(());
+            }
+            let emitter = iface.signal_emitter();
+            zbus::block_on(async {
+                service.count_changed(emitter).await?;
+                service.repo_count_changed(emitter).await?;
+                service.aur_count_changed(emitter).await?;
+                service.updates_changed(emitter).await?;
+                UpdatesService::changed(emitter, service.count()).await
+            })
+        }
     }
 }
 
 // Re-export for easier access
-pub use version_utils::{highlight_semantic_version, is_version_newer, override_columns_from_packages};
+pub use aur::AurBackend;
+pub use cache::{app_dir, fallback_dir, is_fresh, is_fresh_since, SharedCache};
+pub use daemon::{decode_updates, encode_updates, socket_path};
+#[cfg(feature = "dbus")]
+pub use dbus::{send_notification, DbusServer, UpdatesService};
+pub use details::{
+    compare_page, compare_url, details_cache_name, format_details, gitlab_project, parse_commits,
+    version_tag, Commit, DEFAULT_GITLAB_URL,
+};
+pub use history::{
+    format_duration, history_events, install_lags, parse_history, parse_log_time, parse_pacman_log,
+    recorded_updates, HistoryEntry, HistoryEvent, LoggedUpgrade,
+};
+pub use hook::{alpm_hook, catches_signal, is_module_instance, DEFAULT_HOOK_PATH};
+pub use notify::{
+    new_updates, notification, Notification, Notifier, NotifierState, Urgency,
+    DEFAULT_CRITICAL_PACKAGES,
+};
+pub use output::{pango_to_polybar, strip_markup, ModuleState, OutputFormat};
+pub use security::{
+    parse_advisories, security_fix, Advisory, SecurityFix, Severity, DEFAULT_SECURITY_URL,
+};
+pub use theme::{parse_color, Theme, DEFAULT_KIND_COLORS};
+pub use tooltip::{Column, TooltipFormat};
+pub use updates::{
+    drop_installed, fill_text_format, format_size, format_size_delta, group_by_repository,
+    group_split_packages, parse_updates, sort_updates, take_rebuilds, total_sizes,
+    truncation_summary, PackageUpdate, RebuildMode, SortKey,
+};
+pub use upgrade::{
+    shell_quote, terminal_command, upgrade_command, upgrade_script, AUR_HELPERS, TERMINALS,
+};
+pub use version_utils::{
+    display_width, highlight_semantic_version, highlight_version_change, is_devel_package,
+    is_version_newer, override_columns_from_packages, pango_attributes, split_version, update_kind,
+    Alignment, ColumnLayout, UpdateKind,
+};
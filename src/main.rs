@@ -1,10 +1,38 @@
+use alpm::{Alpm, SigLevel};
 use serde::Deserialize;
+use signal_hook::consts::{SIGUSR1, SIGUSR2};
+use signal_hook::iterator::Signals;
+use std::array;
 use std::env;
-use std::io::Error;
-use std::process::Command;
-use std::sync::Mutex;
-use std::{thread, time::Duration, time::SystemTime};
-use waybar_module_pacman_updates::{highlight_semantic_version, is_version_newer, override_columns_from_packages};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Seek, Write};
+use std::io::{Error, ErrorKind};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::{thread, time::Duration, time::SystemTime, time::UNIX_EPOCH};
+use waybar_module_pacman_updates::{
+    alpm_hook, app_dir, catches_signal, compare_page, compare_url, decode_updates,
+    details_cache_name, drop_installed, encode_updates, fallback_dir, fill_text_format,
+    format_details, format_duration, format_size, format_size_delta, group_by_repository,
+    group_split_packages, history_events, install_lags, is_fresh_since, is_module_instance,
+    is_version_newer, pango_attributes, parse_advisories, parse_color, parse_commits,
+    parse_history, parse_pacman_log, parse_updates, recorded_updates, security_fix, socket_path,
+    sort_updates, take_rebuilds, terminal_command, total_sizes, truncation_summary,
+    upgrade_command, upgrade_script, Advisory, Alignment, AurBackend, Column, ColumnLayout, Commit,
+    HistoryEvent, ModuleState, OutputFormat, PackageUpdate, RebuildMode, SharedCache, SortKey,
+    Theme, TooltipFormat, AUR_HELPERS, DEFAULT_CRITICAL_PACKAGES, DEFAULT_GITLAB_URL,
+    DEFAULT_HOOK_PATH, DEFAULT_KIND_COLORS, DEFAULT_SECURITY_URL, TERMINALS,
+};
+#[cfg(feature = "dbus")]
+use waybar_module_pacman_updates::{
+    send_notification, DbusServer, Notifier, NotifierState, UpdatesService,
+};
 
 #[derive(Deserialize)]
 struct AurResponse {
@@ -15,18 +43,74 @@ struct AurResponse {
 struct AurPackage {
     #[serde(rename = "Name")]
     name: String,
+    #[serde(rename = "PackageBase")]
+    package_base: String,
     #[serde(rename = "Version")]
     version: String,
+    #[serde(rename = "LastModified")]
+    last_modified: i64,
 }
 
 lazy_static::lazy_static! {
     static ref DATABASE_SYNC_MUTEX: Mutex<()> = Mutex::new(());
-    // AUR cache: (last_update_time, update_count, formatted_output)
-    static ref AUR_CACHE: Mutex<(Option<SystemTime>, u16, String)> = Mutex::new((None, 0, String::new()));
+    // AUR updates last read from the shared cache, shown while a writer holds its lock
+    static ref LAST_AUR_UPDATES: Mutex<Vec<PackageUpdate>> = Mutex::new(Vec::new());
+    // network requests give up instead of hanging the module on a stalled connection
+    static ref HTTP: ureq::Agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(30))
+        .build();
+    // fixed advisories of the security tracker, empty without --security
+    static ref SECURITY_ADVISORIES: Mutex<Vec<Advisory>> = Mutex::new(Vec::new());
+    // the local database, as configured in pacman.conf
+    static ref DB_PATH: String = pacman_conf(&["DBPath"])
+        .into_iter()
+        .next()
+        .unwrap_or("/var/lib/pacman/".to_string());
 }
 
 fn display_help() {
-    println!("Usage: {} [options]", env::current_exe().unwrap().display());
+    let executable = env::current_exe().unwrap();
+    println!("Usage: {} [options]", executable.display());
+    println!(
+        "       {} install-hook [--path <file>] [--executable <path>]",
+        executable.display()
+    );
+    println!("       {} notify", executable.display());
+    println!(
+        "       {} run-upgrade [--terminal <command>] [--helper <paru|yay|pikaur|pacman>]",
+        executable.display()
+    );
+    println!(
+        "       {} details <package> [--gitlab-url <url>]",
+        executable.display()
+    );
+    println!(
+        "       {} history [--log <file>] [--limit <count>]",
+        executable.display()
+    );
+    println!("       {} daemon [--interval-seconds <seconds>] [--network-interval-seconds <seconds>] [--no-aur]", executable.display());
+    println!();
+    println!("Commands:");
+    println!("  install-hook                   Install a pacman hook that refreshes running instances after each transaction");
+    println!(
+        "                                 (default path: {})",
+        DEFAULT_HOOK_PATH
+    );
+    println!("                                 The executable (default: this one) must only be writable by root");
+    println!("  refresh                        Make running instances sync the databases and the AUR now");
+    println!("  notify                         Make running instances re-render now (used by the pacman hook)");
+    println!("  run-upgrade                    Upgrade in a terminal ($TERMINAL or the first one found) with the first AUR");
+    println!("                                 helper found (paru, yay, pikaur) or pacman, then refresh running instances");
+    println!("  details                        Show the upstream URL and the packaging commits of a pending repo update");
+    println!(
+        "                                 (default GitLab: {})",
+        DEFAULT_GITLAB_URL
+    );
+    println!("  history                        Show the last upgrade, the average time until updates are installed and the");
+    println!(
+        "                                 recent upgrades, from the --history log and pacman.log"
+    );
+    println!("  daemon                         Sync and check once for all instances started with --use-daemon");
     println!();
     println!("Options:");
     println!("  --interval-seconds <seconds>   Set the interval between updates (default: 5)");
@@ -35,34 +119,113 @@ fn display_help() {
         "  --no-zero-output               Don't print '0' when there are no updates available"
     );
     println!("  --no-aur                       Disable checking for AUR updates");
+    println!("  --aur-backend <backend>        Check the AUR with the built-in api, or with paru or yay (-Qua --devel) (default: api)");
     println!("  --tooltip-align-columns <font> Format tooltip as a table using given font (default: monospace)");
-    println!("  --color-semver-updates <colors> Check the difference of semantic versions and color them using the given colors.");
-    println!("                                  The order of pango markup hex colors for colored updates is Major, Minor, Patch, Pre, Other.");
-    println!("                                  (default: ff0000,00ff00,0000ff,ff00ff,ffffff)");
+    println!("  --color-semver-updates <colors> Check the difference of package versions and color them using the given colors.");
+    println!("                                  The order of pango markup hex colors for colored updates is Major, Minor, Patch, Pre, Other,");
+    println!(
+        "                                  Epoch, Rebuild (pkgrel only), Devel (VCS packages)."
+    );
+    println!("                                  (default: ff0000,00ff00,0000ff,ff00ff,ffffff,ff8800,808080,00ffff)");
     println!("  --column-color-overrides <overrides> Overwrites the color of a version column.");
     println!("                                       The column numbers are 1: package_name 2: previous_version 3: arrow 4: new_version.");
     println!("                                       Example: '1=ff0000,4=00ff00' (default: '')");
+    println!("  --theme <name|file>            Colour palette: default, gruvbox, nord, catppuccin, or a GTK/waybar style sheet");
+    println!("                                 with @define-color and .major/.minor/.../.name/.arrow/.aur/.repo rules");
+    println!("                                 Colours may be #rgb, #rrggbb, #rrggbbaa or names everywhere.");
     println!("  --arrow-style <symbol> Changes the style of the arrows, which are displayed between version updates.");
+    println!("  --column-align <alignments>    Align tooltip columns to the left or right when using --tooltip-align-columns.");
+    println!("                                 Example: '2=right,4=right' (default: all left)");
+    println!("  --highlight-version-change <style> Highlight the changed part of the new version with a comma separated style:");
+    println!("                                 bold, italic, underline, color=<hex>, background=<hex> (default: bold)");
+    println!("  --tooltip-columns <columns>    Extra tooltip columns after the new version: repo, size, kind.");
+    println!("                                 Example: 'repo,kind' (columns 5 and 6 for --column-align)");
+    println!("  --group-by-repo                Group tooltip entries under a heading per repository (core, extra, ..., aur)");
+    println!("  --sort <key>                   Sort tooltip entries by name, kind (major first), size, repo or date (newest build first)");
+    println!("  --tooltip-max-lines <lines>    Show at most this many entries in the tooltip, followed by a summary of the rest");
+    println!("  --show-sizes                   Show total download size and installed size change in the tooltip header");
+    println!("  --text-format <format>         Format of the module text. Placeholders: {{count}}, {{download_size}}, {{installed_delta}}");
+    println!("                                 (default: '{{count}}')");
+    println!(
+        "  --notify                       Show a desktop notification when new updates appear"
+    );
+    println!("  --notify-interval <seconds>    Minimum time between notifications, except for critical packages (default: 600)");
+    println!("  --notify-critical <packages>   Packages notified right away with critical urgency");
+    println!(
+        "                                 (default: {})",
+        DEFAULT_CRITICAL_PACKAGES.join(",")
+    );
+    println!("  --security [url|file]          Mark updates fixing vulnerabilities of the Arch security tracker and add the");
+    println!(
+        "                                 'security' class (default: {})",
+        DEFAULT_SECURITY_URL
+    );
+    println!("  --history                      Record when updates appear and are applied in $XDG_STATE_HOME/waybar-module-pacman-updates/history.jsonl");
+    println!("  --dbus                         Publish the updates as org.waybar.PacmanUpdates on the session bus");
+    println!("  --use-daemon                   Get updates from the shared daemon (started when not running) instead of");
+    println!("                                 syncing in every module instance");
+    println!("  --once                         Print the state once and exit with 0 when up to date, 2 with updates, 1 on errors");
+    println!("  --list                         Print the pending updates one per line and exit like --once");
+    println!("  --output <format>              Print the state for waybar, i3blocks, i3bar, polybar, yambar, plain or json");
+    println!("                                 (eww and scripts) (default: waybar)");
+    println!("  --rebuilds <mode>              Show, hide (not counted nor listed) or collapse (one 'N rebuilds' tooltip line)");
+    println!("                                 pkgrel-only rebuilds (default: show)");
+    println!();
+    println!("Signals:");
+    println!("  SIGUSR1                        Sync the databases and the AUR now");
+    println!("  SIGUSR2                        Re-render from local data now");
     println!();
 }
 
 fn main() -> Result<(), Error> {
-    thread::spawn(move || {
-        sync_database();
-    });
     let mut iter: u32 = 0;
+    let (signal_sender, signals) = mpsc::channel();
     let args: Vec<String> = env::args().collect();
+    let mut daemon = false;
+    match args.get(1).map(String::as_str) {
+        Some("install-hook") => return install_hook(&args[2..]),
+        Some("refresh") => return signal_instances(SIGUSR1),
+        Some("notify") => return notify_instances(),
+        Some("run-upgrade") => return run_upgrade(&args[2..]),
+        Some("details") => return show_details(&args[2..]),
+        Some("history") => return show_history(&args[2..]),
+        // takes --interval-seconds, --network-interval-seconds and --no-aur
+        Some("daemon") => daemon = true,
+        _ => {}
+    }
     let mut interval_seconds: u32 = 5;
     let mut network_interval_seconds: u32 = 300;
     let mut clean_output = false;
     let mut tooltip_align = false;
     let mut tooltip_font = "monospace";
     let mut color_semver_updates = false;
-    let mut semver_updates_colors = ["ff0000", "00ff00", "0000ff", "ff00ff", "ffffff"];
+    // empty colours fall back to the theme
+    let mut semver_updates_colors = [""; 8];
     let mut override_column_colors = false;
     let mut column_color_overrides = ["", "", "", ""];
     let mut no_aur = false;
+    let mut aur_backend = AurBackend::Api;
+    let mut security_source: Option<String> = None;
     let mut arrow_style = "->";
+    let mut group_by_repo = false;
+    let mut sort_key: Option<SortKey> = None;
+    let mut tooltip_max_lines: Option<usize> = None;
+    let mut show_sizes = false;
+    let mut text_format = "{count}";
+    let mut rebuild_mode = RebuildMode::Show;
+    let mut column_alignments = [Alignment::Left; 7];
+    let mut extra_columns: Vec<Column> = Vec::new();
+    let mut version_change_attributes: Option<String> = None;
+    let mut theme_source: Option<&str> = None;
+    let mut output_format = OutputFormat::Waybar;
+    let mut once = false;
+    let mut use_daemon = false;
+    let mut dbus = false;
+    let mut notify = false;
+    let mut notify_interval: u64 = 600;
+    let mut critical_packages: Vec<String> = DEFAULT_CRITICAL_PACKAGES.map(String::from).to_vec();
+    let mut list = false;
+    let mut history = false;
     if args.len() > 1 {
         for (i, arg) in args.iter().enumerate() {
             if arg == "--help" {
@@ -80,6 +243,8 @@ fn main() -> Result<(), Error> {
                 clean_output = true;
             } else if arg == "--no-aur" {
                 no_aur = true;
+            } else if arg == "--aur-backend" && i + 1 < args.len() {
+                aur_backend = args[i + 1].parse().unwrap_or_else(|err| panic!("{}", err));
             } else if arg == "--tooltip-align-columns" {
                 tooltip_align = true;
                 if i + 1 < args.len() && args[i + 1][..1] != *"-" {
@@ -122,6 +287,86 @@ fn main() -> Result<(), Error> {
                 }
             } else if arg == "--arrow-style" && i + 1 < args.len() {
                 arrow_style = args[i + 1].as_str();
+            } else if arg == "--group-by-repo" {
+                group_by_repo = true;
+            } else if arg == "--sort" && i + 1 < args.len() {
+                sort_key = Some(args[i + 1].parse().unwrap_or_else(|err| panic!("{}", err)));
+            } else if arg == "--tooltip-max-lines" && i + 1 < args.len() {
+                tooltip_max_lines = match args[i + 1].parse() {
+                    Ok(0) | Err(_) => panic!("--tooltip-max-lines must be greater than 0!"),
+                    Ok(lines) => Some(lines),
+                };
+            } else if arg == "--show-sizes" {
+                show_sizes = true;
+            } else if arg == "--text-format" && i + 1 < args.len() {
+                text_format = args[i + 1].as_str();
+            } else if arg == "--column-align" && i + 1 < args.len() {
+                for setting in args[i + 1].split(',') {
+                    let Some((column, alignment)) = setting.split_once('=') else {
+                        panic!("Invalid column alignment format: '{}'. Expected format: '<num>=<left|right>' (e.g., '2=right,4=right')", setting)
+                    };
+                    let column_index: usize = column.parse().unwrap_or_else(|_| {
+                        panic!(
+                            "Invalid column number '{}'. Must be a number between 1 and 7.",
+                            column
+                        )
+                    });
+                    if column_index == 0 || column_index > 7 {
+                        panic!(
+                            "Column number '{}' is out of range. Valid columns are 1-7.",
+                            column_index
+                        );
+                    }
+                    column_alignments[column_index - 1] =
+                        alignment.parse().unwrap_or_else(|err| panic!("{}", err));
+                }
+            } else if arg == "--tooltip-columns" && i + 1 < args.len() {
+                extra_columns = args[i + 1]
+                    .split(',')
+                    .map(|column| column.parse().unwrap_or_else(|err| panic!("{}", err)))
+                    .collect();
+            } else if arg == "--highlight-version-change" {
+                let mut style = "bold";
+                if i + 1 < args.len() && args[i + 1][..1] != *"-" {
+                    style = args[i + 1].as_str();
+                }
+                version_change_attributes =
+                    Some(pango_attributes(style).unwrap_or_else(|err| panic!("{}", err)));
+            } else if arg == "--security" {
+                let mut source = DEFAULT_SECURITY_URL;
+                if i + 1 < args.len() && args[i + 1][..1] != *"-" {
+                    source = args[i + 1].as_str();
+                }
+                security_source = Some(source.to_string());
+            } else if arg == "--history" {
+                history = true;
+            } else if arg == "--notify" {
+                notify = true;
+            } else if arg == "--notify-interval" && i + 1 < args.len() {
+                notify_interval = args[i + 1]
+                    .parse()
+                    .unwrap_or_else(|_| panic!("--notify-interval must be a number of seconds!"));
+            } else if arg == "--notify-critical" && i + 1 < args.len() {
+                critical_packages = args[i + 1]
+                    .split(',')
+                    .filter(|name| !name.is_empty())
+                    .map(String::from)
+                    .collect();
+            } else if arg == "--dbus" {
+                dbus = true;
+            } else if arg == "--use-daemon" {
+                use_daemon = true;
+            } else if arg == "--once" {
+                once = true;
+            } else if arg == "--list" {
+                once = true;
+                list = true;
+            } else if arg == "--output" && i + 1 < args.len() {
+                output_format = args[i + 1].parse().unwrap_or_else(|err| panic!("{}", err));
+            } else if arg == "--theme" && i + 1 < args.len() {
+                theme_source = Some(args[i + 1].as_str());
+            } else if arg == "--rebuilds" && i + 1 < args.len() {
+                rebuild_mode = args[i + 1].parse().unwrap_or_else(|err| panic!("{}", err));
             }
         }
     }
@@ -130,163 +375,1239 @@ fn main() -> Result<(), Error> {
         panic!("interval-seconds and network-interval-seconds must be greater than 0");
     }
     let update_on_iter = network_interval_seconds / interval_seconds;
+    // a sync by any instance since our last check counts, the slack of one check
+    // keeps our own previous sync from looking fresh
+    let max_age = network_interval_seconds.saturating_sub(interval_seconds);
+    if daemon {
+        return run_daemon(
+            update_on_iter,
+            sleep_duration,
+            max_age,
+            no_aur,
+            aur_backend,
+            security_source,
+        );
+    }
+    let theme = theme_source.map(load_theme).unwrap_or_default();
+    // colours given on the command line win over the theme, all are validated here
+    let kind_colors: [String; 8] = array::from_fn(|slot| match semver_updates_colors[slot] {
+        "" => theme
+            .kind_colors
+            .as_ref()
+            .map_or(DEFAULT_KIND_COLORS[slot].to_string(), |colors| {
+                colors[slot].clone()
+            }),
+        color => parse_color(color).unwrap_or_else(|err| panic!("{}", err)),
+    });
+    let column_colors: [String; 4] =
+        array::from_fn(|column| match column_color_overrides[column] {
+            "" => theme.column_colors[column].clone(),
+            color => parse_color(color).unwrap_or_else(|err| panic!("{}", err)),
+        });
+    let tooltip_format = TooltipFormat {
+        arrow: arrow_style,
+        extra_columns,
+        kind_colors: (color_semver_updates || theme.kind_colors.is_some())
+            .then(|| kind_colors.each_ref().map(String::as_str)),
+        column_colors: (override_column_colors || column_colors.iter().any(|c| !c.is_empty()))
+            .then(|| column_colors.each_ref().map(String::as_str)),
+        version_change_attributes,
+        aur_style: theme.aur_style.clone(),
+        repo_style: theme.repo_style.clone(),
+    };
+    // repository, sizes and build date come from the sync databases
+    let annotate = group_by_repo
+        || sort_key.is_some()
+        || !tooltip_format.extra_columns.is_empty()
+        || show_sizes
+        || text_format.contains("{download_size}")
+        || text_format.contains("{installed_delta}")
+        || notify
+        || security_source.is_some();
+    let (repositories, cache_dirs) = if annotate {
+        (pacman_conf(&["--repo-list"]), pacman_conf(&["CacheDir"]))
+    } else {
+        (Vec::new(), Vec::new())
+    };
+    let render = |mut pending: Vec<PackageUpdate>| {
+        // sizes include rebuilds, they are downloaded on upgrade either way
+        let sizes = total_sizes(&pending);
+        let rebuilds = match rebuild_mode {
+            RebuildMode::Show => Vec::new(),
+            RebuildMode::Hide | RebuildMode::Collapse => take_rebuilds(&mut pending),
+        };
+
+        let updates = match rebuild_mode {
+            RebuildMode::Collapse => pending.len() + rebuilds.len(),
+            RebuildMode::Show | RebuildMode::Hide => pending.len(),
+        };
+
+        if updates > 0 {
+            if let Some(sort_key) = sort_key {
+                sort_updates(&mut pending, sort_key, &repositories);
+            }
+            let mut listed = pending.clone();
+            if rebuild_mode == RebuildMode::Collapse {
+                listed.extend(rebuilds.iter().cloned());
+            }
+            // group headings count every update, including the ones truncated below
+            let group_totals = if group_by_repo {
+                group_by_repository(&pending, &repositories)
+                    .into_iter()
+                    .map(|(repository, members)| (repository, members.len()))
+                    .collect::<Vec<_>>()
+            } else {
+                Vec::new()
+            };
+            // truncate before formatting so markup and alignment only cover shown entries
+            let hidden = match tooltip_max_lines {
+                Some(max_lines) if pending.len() > max_lines => pending.split_off(max_lines),
+                _ => Vec::new(),
+            };
+            // column widths are shared by all groups so the table lines up
+            let padding = if tooltip_align {
+                let rows = pending
+                    .iter()
+                    .map(|update| tooltip_format.cells(update))
+                    .collect::<Vec<_>>();
+                Some(ColumnLayout::fit(&rows, &column_alignments))
+            } else {
+                None
+            };
+
+            let mut stdout = if group_by_repo {
+                group_by_repository(&pending, &repositories)
+                    .into_iter()
+                    .map(|(repository, members)| {
+                        let total = group_totals
+                            .iter()
+                            .find(|(name, _)| *name == repository)
+                            .map_or(members.len(), |(_, total)| *total);
+                        format!(
+                            "<b>{} ({})</b>\n{}",
+                            repository,
+                            total,
+                            tooltip_format.format_updates(&members, padding.as_ref())
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            } else {
+                let shown = pending.iter().collect::<Vec<_>>();
+                tooltip_format.format_updates(&shown, padding.as_ref())
+            };
+            if !hidden.is_empty() {
+                stdout = format!("{}\n{}", stdout, truncation_summary(&hidden));
+            }
+            if rebuild_mode == RebuildMode::Collapse && !rebuilds.is_empty() {
+                let line = match rebuilds.len() {
+                    1 => "1 rebuild".to_string(),
+                    count => format!("{} rebuilds", count),
+                };
+                stdout = if stdout.is_empty() {
+                    line
+                } else {
+                    format!("{}\n{}", stdout, line)
+                };
+            }
+            if show_sizes {
+                stdout = format!(
+                    "<b>Download: {}, Installed: {}</b>\n{}",
+                    format_size(sizes.0),
+                    format_size_delta(sizes.1),
+                    stdout
+                );
+            }
+
+            if tooltip_align {
+                stdout = format!("<span font-family='{}'>{}</span>", tooltip_font, stdout);
+            }
+            ModuleState {
+                text: fill_text_format(text_format, updates, sizes),
+                tooltip: stdout.trim_end().to_string(),
+                class: "has-updates",
+                security: listed.iter().any(|update| update.security.is_some()),
+                updates: listed,
+            }
+        } else {
+            ModuleState {
+                text: if clean_output {
+                    String::new()
+                } else {
+                    fill_text_format(text_format, 0, sizes)
+                },
+                tooltip: "System updated".to_string(),
+                class: "updated",
+                security: false,
+                updates: Vec::new(),
+            }
+        }
+    };
+    #[cfg(not(feature = "dbus"))]
+    if dbus || notify {
+        panic!("--dbus and --notify need a build with the dbus feature");
+    }
+    #[cfg(not(feature = "dbus"))]
+    let _ = (notify_interval, critical_packages);
+    #[cfg(feature = "dbus")]
+    let dbus_server = if dbus {
+        // the daemon does the syncing for its clients
+        let refresh_sender = signal_sender.clone();
+        let service = UpdatesService::new(move || {
+            if use_daemon {
+                let _ = signal_instances(SIGUSR1);
+            } else {
+                let _ = refresh_sender.send((SIGUSR1, unix_now()));
+            }
+        });
+        DbusServer::start(service)
+            .map_err(|err| eprintln!("Cannot start the D-Bus service: {}", err))
+            .ok()
+    } else {
+        None
+    };
+    #[cfg(feature = "dbus")]
+    let mut notifications = if notify {
+        match zbus::blocking::Connection::session() {
+            Ok(connection) => Some((
+                connection,
+                Notifier::new(notify_interval, critical_packages, !no_aur),
+                NotifierState::default(),
+            )),
+            Err(err) => {
+                eprintln!(
+                    "Cannot connect to the session bus for notifications: {}",
+                    err
+                );
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let mut recorded: Option<Vec<PackageUpdate>> = None;
+    let mut emit = |pending: Vec<PackageUpdate>| {
+        // only lock the history when this instance sees a change
+        if history
+            && recorded
+                .as_ref()
+                .is_none_or(|recorded| !history_events(recorded, &pending, 0).is_empty())
+        {
+            if let Err(err) = record_history(&pending, !no_aur) {
+                eprintln!("{}", err);
+            }
+            recorded = Some(pending.clone());
+        }
+        let state = render(pending);
+        #[cfg(feature = "dbus")]
+        if let Some((connection, notifier, local)) = &mut notifications {
+            // share what was notified with the other instances, or keep it here
+            // without a usable cache file
+            let notification = match lock_cache(libc::LOCK_EX) {
+                Ok((mut file, mut cache)) => {
+                    let previous = cache.notifications.clone();
+                    let notification = notifier.update(
+                        &mut cache.notifications,
+                        &state.updates,
+                        &repositories,
+                        unix_now(),
+                    );
+                    // most intervals change nothing, so the cache isn't rewritten
+                    if cache.notifications != previous {
+                        save_cache(&mut file, &cache);
+                    }
+                    notification
+                }
+                Err(_) => notifier.update(local, &state.updates, &repositories, unix_now()),
+            };
+            if let Some(notification) = notification {
+                if let Err(err) = send_notification(connection, &notification) {
+                    eprintln!("Cannot send notification: {}", err);
+                }
+            }
+        }
+        #[cfg(feature = "dbus")]
+        if let Some(server) = &dbus_server {
+            if let Err(err) = server.publish(&state.updates) {
+                eprintln!("Cannot publish updates on D-Bus: {}", err);
+            }
+        }
+        if list {
+            for update in &state.updates {
+                println!("{}", update);
+            }
+        } else {
+            println!("{}", output_format.render(&state));
+        }
+        if once {
+            // 0 up to date, 2 updates available (errors exit with 1 above)
+            process::exit(if state.updates.is_empty() { 0 } else { 2 });
+        }
+    };
+    if let Some(header) = output_format.header().filter(|_| !list) {
+        println!("{}", header);
+    }
+    if use_daemon {
+        // the daemon handles refresh signals, they must not terminate its clients
+        let mut handled = Signals::new([SIGUSR1, SIGUSR2])?;
+        thread::spawn(move || handled.forever().for_each(drop));
+        let mut daemon_args = vec![
+            "--interval-seconds".to_string(),
+            interval_seconds.to_string(),
+            "--network-interval-seconds".to_string(),
+            network_interval_seconds.to_string(),
+        ];
+        if no_aur {
+            daemon_args.push("--no-aur".to_string());
+        }
+        daemon_args.extend(["--aur-backend".to_string(), aur_backend.name().to_string()]);
+        if let Some(source) = security_source {
+            daemon_args.extend(["--security".to_string(), source]);
+        }
+        return subscribe(&daemon_args, emit);
+    }
+    if once {
+        // sync before the only check instead of in the background
+        iter = update_on_iter;
+    } else {
+        let startup_security = security_source.clone();
+        thread::spawn(move || {
+            // the AUR too, a list from before a reboot would stay up for a whole interval
+            if let Err(err) = sync_all(no_aur, aur_backend, startup_security.as_deref(), max_age, 0)
+            {
+                eprintln!("{}", err);
+            }
+        });
+        listen_for_signals(signal_sender)?;
+    }
+    // time of the last SIGUSR1, syncs from before it don't count as fresh
+    let mut requested = 0;
     loop {
         if iter >= update_on_iter {
-            sync_database();
-            if !no_aur {
-                sync_aur_database(network_interval_seconds);
+            if let Err(err) = sync_all(
+                no_aur,
+                aur_backend,
+                security_source.as_deref(),
+                max_age,
+                requested,
+            ) {
+                eprintln!("{}", err);
+                if once {
+                    process::exit(1);
+                }
             }
             iter = 0;
         }
-        let (pacman_updates, pacman_stdout) = get_updates();
-        let (aur_updates, aur_stdout) = if no_aur {
-            (0, String::new())
-        } else {
-            get_aur_updates()
-        };
+        let annotation = annotate.then_some((repositories.as_slice(), cache_dirs.as_slice()));
+        match check_updates(no_aur, annotation) {
+            Ok(pending) => emit(pending),
+            Err(err) => {
+                // keep showing the last state
+                eprintln!("{}", err);
+                if once {
+                    process::exit(1);
+                }
+            }
+        }
+        match wait_for_refresh(&signals, sleep_duration) {
+            Some(time) => {
+                requested = time;
+                iter = update_on_iter;
+            }
+            None => iter += 1,
+        }
+    }
+}
 
-        let updates = pacman_updates + aur_updates;
-        let mut stdout = if !aur_stdout.is_empty() && !pacman_stdout.is_empty() {
-            format!("{}\n{}", pacman_stdout, aur_stdout)
-        } else if !aur_stdout.is_empty() {
-            aur_stdout
-        } else {
-            pacman_stdout
-        };
+// SIGUSR1 forces a network sync, SIGUSR2 re-renders from local data
+fn listen_for_signals(sender: Sender<(i32, u64)>) -> Result<(), Error> {
+    let mut handled = Signals::new([SIGUSR1, SIGUSR2])?;
+    thread::spawn(move || {
+        for signal in handled.forever() {
+            if sender.send((signal, unix_now())).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(())
+}
 
-        if updates > 0 {
-            if arrow_style != "->" {
-                stdout = stdout.replace("->", arrow_style);
+// Sleep until the next check or a refresh signal, with the time of the last SIGUSR1
+// when a network sync was requested. A signal only triggers one refresh however many
+// queued up during a slow sync.
+fn wait_for_refresh(signals: &Receiver<(i32, u64)>, timeout: Duration) -> Option<u64> {
+    match signals.recv_timeout(timeout) {
+        Ok(first) => std::iter::once(first)
+            .chain(signals.try_iter())
+            .filter(|(signal, _)| *signal == SIGUSR1)
+            .map(|(_, time)| time)
+            .max(),
+        Err(RecvTimeoutError::Timeout) => None,
+        // the signal thread is gone, so just wait out the interval
+        Err(RecvTimeoutError::Disconnected) => {
+            thread::sleep(timeout);
+            None
+        }
+    }
+}
+
+// write the ALPM hook that runs `notify` after each pacman transaction
+fn install_hook(args: &[String]) -> Result<(), Error> {
+    let mut path = DEFAULT_HOOK_PATH;
+    let mut executable = env::current_exe()?;
+    for (i, arg) in args.iter().enumerate() {
+        if arg == "--path" && i + 1 < args.len() {
+            path = args[i + 1].as_str();
+        } else if arg == "--executable" && i + 1 < args.len() {
+            executable = PathBuf::from(&args[i + 1]);
+        }
+    }
+    let executable = fs::canonicalize(&executable)?;
+    if let Err(err) = check_root_only(&executable) {
+        eprintln!("{}", err);
+        eprintln!("The hook runs as root, install the module to a system path such as /usr/bin or pass --executable <path>");
+        process::exit(1);
+    }
+    let hook = alpm_hook(&executable.display().to_string());
+    let written = match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => fs::create_dir_all(dir),
+        _ => Ok(()),
+    }
+    .and_then(|_| fs::write(path, hook));
+    if let Err(err) = written {
+        eprintln!("Cannot write {}: {}", path, err);
+        process::exit(1);
+    }
+    println!("Installed pacman hook to {}", path);
+    Ok(())
+}
+
+// `path` and every directory above it must be owned by root and not writable by
+// anyone else, otherwise a user could swap what the hook runs as root
+fn check_root_only(path: &Path) -> Result<(), String> {
+    for current in path.ancestors() {
+        let metadata = fs::metadata(current)
+            .map_err(|err| format!("Cannot inspect {}: {}", current.display(), err))?;
+        if metadata.uid() != 0 || metadata.mode() & 0o022 != 0 {
+            return Err(format!(
+                "{} must be owned by root and not writable by group or others",
+                current.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+// upgrade in a terminal, then make running instances re-render so they drop what was upgraded
+fn run_upgrade(args: &[String]) -> Result<(), Error> {
+    let mut terminal = env::var("TERMINAL")
+        .ok()
+        .filter(|terminal| !terminal.is_empty());
+    let mut helper = AUR_HELPERS
+        .into_iter()
+        .find(|helper| is_installed(helper))
+        .map(String::from);
+    for (i, arg) in args.iter().enumerate() {
+        if arg == "--terminal" && i + 1 < args.len() {
+            terminal = Some(args[i + 1].clone());
+        } else if arg == "--helper" && i + 1 < args.len() {
+            // "pacman" upgrades without an AUR helper
+            helper = Some(args[i + 1].clone()).filter(|helper| helper != "pacman");
+        }
+    }
+    let Some(terminal) = terminal.or_else(|| {
+        TERMINALS
+            .into_iter()
+            .find(|terminal| is_installed(terminal))
+            .map(String::from)
+    }) else {
+        eprintln!("No terminal emulator found, set $TERMINAL or use --terminal");
+        process::exit(1);
+    };
+
+    let executable = env::current_exe()?;
+    let script = upgrade_script(
+        &upgrade_command(helper.as_deref()),
+        &executable.display().to_string(),
+    );
+    let argv = terminal_command(&terminal, &["sh".to_string(), "-c".to_string(), script]);
+    if let Err(err) = Command::new(&argv[0]).args(&argv[1..]).status() {
+        eprintln!("Cannot run {}: {}", argv[0], err);
+        process::exit(1);
+    }
+    Ok(())
+}
+
+// print the upstream URL and the packaging commits between the installed and
+// the new version of a pending repo update
+fn show_details(args: &[String]) -> Result<(), Error> {
+    let mut package: Option<&str> = None;
+    let mut gitlab_url = DEFAULT_GITLAB_URL;
+    for (i, arg) in args.iter().enumerate() {
+        if arg == "--gitlab-url" && i + 1 < args.len() {
+            gitlab_url = args[i + 1].as_str();
+        } else if !arg.starts_with('-') && (i == 0 || args[i - 1] != "--gitlab-url") {
+            package = Some(arg.as_str());
+        }
+    }
+    let Some(package) = package else {
+        eprintln!("Usage: details <package> [--gitlab-url <url>]");
+        process::exit(1);
+    };
+    let update = match get_updates() {
+        Ok(updates) => updates.into_iter().find(|update| update.name == package),
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
+    let Some(update) = update else {
+        eprintln!("{} has no pending repository update", package);
+        process::exit(1);
+    };
+
+    // the packaging repository is named after the base of split packages
+    let mut pkgbase = update.name.clone();
+    let mut upstream = None;
+    if let Ok(handle) = Alpm::new("/".to_string(), checkupdates_db_path()) {
+        for repository in pacman_conf(&["--repo-list"]) {
+            let _ = handle.register_syncdb(repository, SigLevel::USE_DEFAULT);
+        }
+        if let Some(pkg) = handle
+            .syncdbs()
+            .into_iter()
+            .find_map(|db| db.pkg(update.name.as_str()).ok())
+        {
+            pkgbase = pkg.base().unwrap_or(pkg.name()).to_string();
+            upstream = pkg.url().map(String::from);
+        }
+    }
+
+    let page = compare_page(gitlab_url, &pkgbase, &update);
+    match packaging_commits(gitlab_url, &pkgbase, &update) {
+        Ok(commits) => {
+            println!(
+                "{}",
+                format_details(&update, upstream.as_deref(), &page, &commits)
+            );
+            Ok(())
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+}
+
+// Commits between two tags never change, so every comparison is cached for good
+fn packaging_commits(
+    gitlab_url: &str,
+    pkgbase: &str,
+    update: &PackageUpdate,
+) -> Result<Vec<Commit>, String> {
+    let path = cache_file_path()
+        .with_file_name("details")
+        .join(details_cache_name(gitlab_url, pkgbase, update));
+    if let Ok(commits) = fs::read_to_string(&path)
+        .map_err(|err| err.to_string())
+        .and_then(|json| parse_commits(&json))
+    {
+        return Ok(commits);
+    }
+
+    let json = HTTP
+        .get(&compare_url(gitlab_url, pkgbase, update))
+        .call()
+        .map_err(|err| format!("Cannot fetch the packaging commits of {}: {}", pkgbase, err))?
+        .into_string()
+        .map_err(|err| format!("Cannot fetch the packaging commits of {}: {}", pkgbase, err))?;
+    let commits = parse_commits(&json)?;
+    // keep only the commits, the response also holds the whole diff
+    let _ = write_atomic(
+        &path,
+        &serde_json::json!({ "commits": commits }).to_string(),
+    );
+    Ok(commits)
+}
+
+fn is_installed(program: &str) -> bool {
+    env::var_os("PATH")
+        .is_some_and(|path| env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+// send SIGUSR2 to every running instance so they re-render from the new local database
+fn notify_instances() -> Result<(), Error> {
+    signal_instances(SIGUSR2)
+}
+
+fn signal_instances(signal: i32) -> Result<(), Error> {
+    for entry in fs::read_dir("/proc")?.flatten() {
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<i32>().ok())
+        else {
+            continue;
+        };
+        if pid as u32 == process::id() {
+            continue;
+        }
+        // processes may exit or belong to users we can't inspect
+        let Ok(cmdline) = fs::read(entry.path().join("cmdline")) else {
+            continue;
+        };
+        if !is_module_instance(&String::from_utf8_lossy(&cmdline)) {
+            continue;
+        }
+        // skip instances that haven't set up their handler yet, the signal would kill them
+        let Ok(status) = fs::read_to_string(entry.path().join("status")) else {
+            continue;
+        };
+        if catches_signal(&status, signal) {
+            unsafe {
+                libc::kill(pid, signal);
             }
+        }
+    }
+    Ok(())
+}
 
-            if tooltip_align {
-                let mut padding = [0; 4];
-                stdout
-                    .split_whitespace()
-                    .enumerate()
-                    .for_each(|(index, word)| {
-                        padding[index % 4] = padding[index % 4].max(word.len())
-                    });
+// own syncing for every module instance of the session and send them the
+// pending updates over the daemon socket whenever they change
+fn run_daemon(
+    update_on_iter: u32,
+    sleep_duration: Duration,
+    max_age: u32,
+    no_aur: bool,
+    aur_backend: AurBackend,
+    security_source: Option<String>,
+) -> Result<(), Error> {
+    let path = daemon_socket_path()?;
+    // the lock is held for the daemon's lifetime, so concurrently spawned daemons step back
+    let lock = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path.with_extension("lock"))?;
+    if unsafe { libc::flock(lock.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        return Ok(());
+    }
+    // a socket left behind by a daemon that didn't exit cleanly
+    let _ = fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
 
-                if color_semver_updates {
-                    stdout =
-                        highlight_semantic_version(stdout, semver_updates_colors, override_column_colors, column_color_overrides, Some(padding));
-                } else if override_column_colors {
-                    stdout =
-                        override_columns_from_packages(stdout, column_color_overrides, Some(padding));
-                } else {
-                    stdout = stdout
-                        .split_whitespace()
-                        .enumerate()
-                        .map(|(index, word)| {
-                            word.to_string() + " ".repeat(padding[index % 4] - word.len()).as_str()
-                        })
-                        .collect::<Vec<String>>()
-                        .chunks(4)
-                        .map(|line| line.join(" "))
-                        .collect::<Vec<String>>()
-                        .join("\n");
+    // (last published line, subscribers)
+    let subscribers: Arc<Mutex<(Option<String>, Vec<UnixStream>)>> =
+        Arc::new(Mutex::new((None, Vec::new())));
+    let accepting = Arc::clone(&subscribers);
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            // a stuck client must not block the others
+            let _ = stream.set_write_timeout(Some(Duration::from_secs(1)));
+            let mut subscribers = accepting.lock().unwrap();
+            if let Some(line) = &subscribers.0 {
+                if writeln!(stream, "{}", line).is_err() {
+                    continue;
                 }
+            }
+            subscribers.1.push(stream);
+        }
+    });
 
-                stdout = format!("<span font-family='{}'>{}</span>", tooltip_font, stdout);
-            } else if color_semver_updates {
-                stdout = highlight_semantic_version(stdout, semver_updates_colors, override_column_colors, column_color_overrides, None);
+    let startup_security = security_source.clone();
+    thread::spawn(move || {
+        if let Err(err) = sync_all(no_aur, aur_backend, startup_security.as_deref(), max_age, 0) {
+            eprintln!("{}", err);
+        }
+    });
+    let (signal_sender, signals) = mpsc::channel();
+    listen_for_signals(signal_sender)?;
+    let repositories = pacman_conf(&["--repo-list"]);
+    let cache_dirs = pacman_conf(&["CacheDir"]);
+    let mut iter: u32 = 0;
+    let mut requested = 0;
+    loop {
+        if iter >= update_on_iter {
+            if let Err(err) = sync_all(
+                no_aur,
+                aur_backend,
+                security_source.as_deref(),
+                max_age,
+                requested,
+            ) {
+                eprintln!("{}", err);
+            }
+            iter = 0;
+        }
+        // clients may need any annotation, depending on their options
+        match check_updates(no_aur, Some((&repositories, &cache_dirs))) {
+            Ok(pending) => {
+                let line = encode_updates(&pending);
+                let mut subscribers = subscribers.lock().unwrap();
+                if subscribers.0.as_ref() != Some(&line) {
+                    subscribers
+                        .1
+                        .retain_mut(|stream| writeln!(stream, "{}", line).is_ok());
+                    subscribers.0 = Some(line);
+                }
+            }
+            Err(err) => eprintln!("{}", err),
+        }
+        match wait_for_refresh(&signals, sleep_duration) {
+            Some(time) => {
+                requested = time;
+                iter = update_on_iter;
+            }
+            None => iter += 1,
+        }
+    }
+}
+
+// render every update list the daemon sends, starting the daemon when none is running
+fn subscribe(
+    daemon_args: &[String],
+    mut emit: impl FnMut(Vec<PackageUpdate>),
+) -> Result<(), Error> {
+    let path = daemon_socket_path()?;
+    loop {
+        let stream = match UnixStream::connect(&path) {
+            Ok(stream) => stream,
+            Err(_) => spawn_daemon(&path, daemon_args)?,
+        };
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            match decode_updates(&line) {
+                Ok(pending) => emit(pending),
+                Err(err) => eprintln!("{}", err),
+            }
+        }
+        // the daemon went away, the next connection attempt replaces it
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+fn spawn_daemon(path: &Path, daemon_args: &[String]) -> Result<UnixStream, Error> {
+    Command::new(env::current_exe()?)
+        .arg("daemon")
+        .args(daemon_args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        // outlive the bar that started it
+        .process_group(0)
+        .spawn()?;
+    for _ in 0..50 {
+        thread::sleep(Duration::from_millis(100));
+        if let Ok(stream) = UnixStream::connect(path) {
+            return Ok(stream);
+        }
+    }
+    UnixStream::connect(path)
+}
+
+fn daemon_socket_path() -> Result<PathBuf, Error> {
+    let path = socket_path(env::var("XDG_RUNTIME_DIR").ok().as_deref(), current_uid());
+    if let Some(dir) = path.parent() {
+        create_app_dir(dir)?;
+    }
+    Ok(path)
+}
+
+// sync the repositories and, unless disabled, the AUR, unless another
+// instance did so less than `max_age` seconds ago and not before `requested`
+fn sync_all(
+    no_aur: bool,
+    aur_backend: AurBackend,
+    security_source: Option<&str>,
+    max_age: u32,
+    requested: u64,
+) -> Result<(), String> {
+    let mut synced = sync_database(max_age, requested);
+    if !no_aur {
+        synced = synced.and(sync_aur_database(max_age, requested, aur_backend));
+    }
+    if let Some(source) = security_source {
+        synced = synced.and(sync_security(source, max_age, requested));
+    }
+    synced
+}
+
+// Load the security tracker data from a URL or a local file. Downloads are kept
+// next to the shared cache, so instances don't fetch them every interval.
+fn sync_security(source: &str, max_age: u32, requested: u64) -> Result<(), String> {
+    let json = if source.starts_with("http://") || source.starts_with("https://") {
+        let path = cache_file_path().with_file_name("security.json");
+        let downloaded = fs::metadata(&path)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|age| age.as_secs());
+        if is_fresh_since(downloaded, unix_now(), max_age as u64, requested) {
+            if !SECURITY_ADVISORIES.lock().unwrap().is_empty() {
+                return Ok(());
             }
-            let tooltip = stdout.trim_end().replace("\"", "\\\"").replace("\n", "\\n");
-            println!("{{\"text\":\"{}\",\"tooltip\":\"{}\",\"class\":\"has-updates\",\"alt\":\"has-updates\"}}", updates, tooltip);
+            fs::read_to_string(&path).map_err(|err| format!("Cannot read {:?}: {}", path, err))?
         } else {
-            println!("{{\"text\":{},\"tooltip\":\"System updated\",\"class\": \"updated\",\"alt\":\"updated\"}}", if clean_output {"\"\""} else {"\"0\""});
+            let downloaded = HTTP
+                .get(source)
+                .call()
+                .map_err(|err| err.to_string())
+                .and_then(|response| response.into_string().map_err(|err| err.to_string()));
+            match downloaded {
+                Ok(json) => {
+                    if let Err(err) = write_atomic(&path, &json) {
+                        eprintln!("Cannot write {}: {}", path.display(), err);
+                    }
+                    json
+                }
+                // offline: keep the advisories we have, or the last download
+                Err(err) => {
+                    eprintln!("Failed to fetch security advisories: {}", err);
+                    if !SECURITY_ADVISORIES.lock().unwrap().is_empty() {
+                        return Ok(());
+                    }
+                    fs::read_to_string(&path)
+                        .map_err(|_| format!("Failed to fetch security advisories: {}", err))?
+                }
+            }
+        }
+    } else {
+        fs::read_to_string(source)
+            .map_err(|err| format!("Cannot read security advisories '{}': {}", source, err))?
+    };
+    *SECURITY_ADVISORIES.lock().unwrap() = parse_advisories(&json)?;
+    Ok(())
+}
+
+// $XDG_CACHE_HOME/waybar-module-pacman-updates/cache.json
+fn cache_file_path() -> PathBuf {
+    let xdg_dir = env::var("XDG_CACHE_HOME").ok();
+    let home = env::var("HOME").ok();
+    app_dir(xdg_dir.as_deref(), home.as_deref(), ".cache", current_uid()).join("cache.json")
+}
+
+// Open the cache file shared by all instances and flock it with `operation` until
+// the file is dropped. It is only held to read or write it, never across network I/O.
+fn lock_cache(operation: libc::c_int) -> Result<(fs::File, SharedCache), String> {
+    let (file, contents) = lock_file(&cache_file_path(), "cache", operation)?;
+    Ok((file, SharedCache::parse(&contents)))
+}
+
+// Take sync.lock until the file is dropped, so one instance syncs at a time and
+// the others wait for its result instead of syncing again
+fn lock_sync() -> Result<fs::File, String> {
+    let (file, _) = lock_file(
+        &cache_file_path().with_file_name("sync.lock"),
+        "sync lock",
+        libc::LOCK_EX,
+    )?;
+    Ok(file)
+}
+
+// Apply `change` to the shared cache under a short exclusive lock
+fn update_cache(change: impl FnOnce(&mut SharedCache)) {
+    if let Ok((mut file, mut cache)) = lock_cache(libc::LOCK_EX) {
+        change(&mut cache);
+        save_cache(&mut file, &cache);
+    }
+}
+
+// rewrite the cache while still holding the exclusive lock
+fn save_cache(file: &mut fs::File, cache: &SharedCache) {
+    if let Err(err) = rewrite_locked(file, &cache.to_json()) {
+        eprintln!("Cannot write cache: {}", err);
+    }
+}
+
+// open (or create) a file and its directory, flock it and read its contents.
+// `what` names the file in errors, e.g. "cache".
+fn lock_file(
+    path: &Path,
+    what: &str,
+    operation: libc::c_int,
+) -> Result<(fs::File, String), String> {
+    let opened = match path.parent() {
+        Some(dir) => create_app_dir(dir),
+        None => Ok(()),
+    }
+    .and_then(|_| {
+        fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .custom_flags(libc::O_NOFOLLOW)
+            .open(path)
+    });
+    let mut file =
+        opened.map_err(|err| format!("Cannot open {} {}: {}", what, path.display(), err))?;
+    if unsafe { libc::flock(file.as_raw_fd(), operation) } != 0 {
+        return Err(format!(
+            "Cannot lock {} {}: {}",
+            what,
+            path.display(),
+            Error::last_os_error()
+        ));
+    }
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)
+        .map_err(|err| format!("Cannot read {} {}: {}", what, path.display(), err))?;
+    Ok((file, contents))
+}
+
+// Create `dir` and its parents. Without $HOME or $XDG_RUNTIME_DIR the files live in
+// /tmp, where the directory has to be the user's own and private, not one planted
+// by someone else.
+fn create_app_dir(dir: &Path) -> Result<(), Error> {
+    let uid = current_uid();
+    let private = fallback_dir(uid);
+    if dir.starts_with(&private) {
+        if let Err(err) = fs::DirBuilder::new().mode(0o700).create(&private) {
+            if err.kind() != ErrorKind::AlreadyExists {
+                return Err(err);
+            }
         }
-        iter += 1;
-        thread::sleep(sleep_duration);
+        let metadata = fs::symlink_metadata(&private)?;
+        if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                format!(
+                    "{} is not a private directory of this user",
+                    private.display()
+                ),
+            ));
+        }
+    }
+    fs::create_dir_all(dir)
+}
+
+// Write through a temporary file that is renamed over `path`, so readers never
+// see a partial file
+fn write_atomic(path: &Path, contents: &str) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+        create_app_dir(dir)?;
     }
+    let temporary = path.with_extension(format!("tmp.{}", process::id()));
+    fs::write(&temporary, contents)
+        .and_then(|_| fs::rename(&temporary, path))
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&temporary);
+        })
+}
+
+fn rewrite_locked(file: &mut fs::File, contents: &str) -> Result<(), Error> {
+    file.set_len(0)?;
+    file.rewind()?;
+    file.write_all(contents.as_bytes())
+}
+
+// $XDG_STATE_HOME/waybar-module-pacman-updates, holding the update history
+fn state_dir() -> PathBuf {
+    let xdg_dir = env::var("XDG_STATE_HOME").ok();
+    let home = env::var("HOME").ok();
+    app_dir(
+        xdg_dir.as_deref(),
+        home.as_deref(),
+        ".local/state",
+        current_uid(),
+    )
+}
+
+// Append the transitions since the last recorded pending updates to history.jsonl.
+// The recorded updates are locked, so instances sharing them log each transition once.
+// Without `aur` (--no-aur) the recorded AUR updates are left as they are.
+fn record_history(current: &[PackageUpdate], aur: bool) -> Result<(), String> {
+    let dir = state_dir();
+    let pending = dir.join("pending.json");
+    let (mut file, contents) = lock_file(&pending, "history", libc::LOCK_EX)?;
+    let recorded = match decode_updates(&contents) {
+        Ok(previous) => {
+            let recorded = recorded_updates(&previous, current, aur);
+            let events = history_events(&previous, &recorded, unix_now());
+            if events.is_empty() {
+                return Ok(());
+            }
+            let lines = events
+                .iter()
+                .map(|event| {
+                    serde_json::to_string(event).expect("history entries are always serializable")
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            let path = dir.join("history.jsonl");
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .and_then(|mut history| writeln!(history, "{}", lines))
+                .map_err(|err| format!("Cannot write {}: {}", path.display(), err))?;
+            recorded
+        }
+        // nothing to compare with on the first run, only record what is pending
+        Err(_) => current.to_vec(),
+    };
+    rewrite_locked(&mut file, &encode_updates(&recorded))
+        .map_err(|err| format!("Cannot write {}: {}", pending.display(), err))
+}
+
+// print statistics about the recorded history and the upgrades in pacman.log
+fn show_history(args: &[String]) -> Result<(), Error> {
+    let mut log_file = pacman_conf(&["LogFile"])
+        .into_iter()
+        .next()
+        .unwrap_or("/var/log/pacman.log".to_string());
+    let mut limit: usize = 10;
+    for (i, arg) in args.iter().enumerate() {
+        if arg == "--log" && i + 1 < args.len() {
+            log_file = args[i + 1].clone();
+        } else if arg == "--limit" && i + 1 < args.len() {
+            limit = args[i + 1]
+                .parse()
+                .unwrap_or_else(|_| panic!("--limit must be a number"));
+        }
+    }
+    let history =
+        parse_history(&fs::read_to_string(state_dir().join("history.jsonl")).unwrap_or_default());
+    let upgrades = match fs::read_to_string(&log_file) {
+        Ok(log) => parse_pacman_log(&log),
+        Err(err) => {
+            eprintln!("Cannot read {}: {}", log_file, err);
+            Vec::new()
+        }
+    };
+
+    match upgrades.last() {
+        Some(upgrade) => println!("Last upgrade: {}", upgrade.timestamp),
+        None => println!("Last upgrade: unknown"),
+    }
+    let count = |event| history.iter().filter(|entry| entry.event == event).count();
+    println!(
+        "Recorded: {} updates available, {} applied",
+        count(HistoryEvent::Available),
+        count(HistoryEvent::Applied)
+    );
+    let lags = install_lags(&history, &upgrades);
+    if lags.is_empty() {
+        println!(
+            "Average lag: unknown (no recorded update found in {})",
+            log_file
+        );
+        return Ok(());
+    }
+    let average = lags.iter().map(|(_, _, lag)| lag).sum::<i64>() / lags.len() as i64;
+    println!(
+        "Average lag: {} ({} updates installed)",
+        format_duration(average),
+        lags.len()
+    );
+    println!("Recent upgrades:");
+    for (entry, upgrade, lag) in lags.iter().rev().take(limit) {
+        println!(
+            "  {} {} -> {} installed {} after {}",
+            entry.name,
+            entry.old_version,
+            entry.new_version,
+            upgrade.timestamp,
+            format_duration(*lag)
+        );
+    }
+    Ok(())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+// pending repo and AUR updates from the local data, annotated from the sync
+// databases when (repositories, cache dirs) are given
+fn check_updates(
+    no_aur: bool,
+    annotation: Option<(&[String], &[String])>,
+) -> Result<Vec<PackageUpdate>, String> {
+    let mut pending = get_updates()?;
+    if !no_aur {
+        pending.extend(get_aur_updates());
+    }
+    if let Some((repositories, cache_dirs)) = annotation {
+        if !pending.is_empty() {
+            annotate_updates(&mut pending, repositories, cache_dirs);
+        }
+    }
+    // the tracker only covers the official repositories
+    let advisories = SECURITY_ADVISORIES.lock().unwrap();
+    if !advisories.is_empty() {
+        for update in pending.iter_mut() {
+            if update.repository.as_deref() != Some("aur") {
+                update.security = security_fix(update, &advisories);
+            }
+        }
+    }
+    Ok(pending)
 }
 
 // check updates from network
-fn sync_database() {
-    let _lock = DATABASE_SYNC_MUTEX.lock().unwrap();
+fn sync_database(max_age: u32, requested: u64) -> Result<(), String> {
+    // a sync already running (e.g. the startup one) is waited for, and its
+    // result checked like one from another instance
+    let _lock = DATABASE_SYNC_MUTEX
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+    // without a usable cache file every instance syncs on its own
+    let _sync = lock_sync().ok();
+    let now = unix_now();
+    if lock_cache(libc::LOCK_SH)
+        .is_ok_and(|(_, cache)| is_fresh_since(cache.repo_synced, now, max_age as u64, requested))
+    {
+        return Ok(());
+    }
     // checkupdates --nocolor
-    Command::new("checkupdates")
+    let output = Command::new("checkupdates")
         .args(["--nocolor"])
         .output()
-        .expect("failed to execute process");
+        .map_err(|err| format!("failed to execute checkupdates: {}", err))?;
+    checkupdates_result(&output)?;
+    update_cache(|cache| cache.repo_synced = Some(now));
+    Ok(())
+}
+
+// checkupdates exits with 0 when there are updates, 2 when there are none and 1 on errors
+fn checkupdates_result(output: &Output) -> Result<String, String> {
+    match output.status.code() {
+        Some(0) | Some(2) => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        _ => Err(format!(
+            "checkupdates failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+    }
 }
 
 // check AUR updates from network
-fn sync_aur_database(network_interval_seconds: u32) {
-    // Lock AUR cache to read/update: (last_update_time, update_count, formatted_output)
-    let mut cache = AUR_CACHE.lock().unwrap();
-    let now = SystemTime::now();
+fn sync_aur_database(max_age: u32, requested: u64, backend: AurBackend) -> Result<(), String> {
+    // Other instances wait here and then find the cache fresh
+    let _sync = lock_sync()?;
+    let now = unix_now();
 
-    if let Some(last_update) = cache.0 {
-        if let Ok(elapsed) = now.duration_since(last_update) {
-            if elapsed.as_secs() < network_interval_seconds as u64 {
-                return;
+    let (_, cache) = lock_cache(libc::LOCK_SH)?;
+    if is_fresh_since(cache.aur_synced, now, max_age as u64, requested) {
+        return Ok(());
+    }
+
+    if let Some(command) = backend.helper_command() {
+        let updates = query_aur_helper(&command);
+        // like API failures, failed helper runs aren't retried before the next interval
+        update_cache(|cache| {
+            cache.aur_synced = Some(now);
+            if let Ok(updates) = &updates {
+                cache.aur_updates = updates.clone();
             }
-        }
+        });
+        return updates.map(|_| ());
     }
 
     // Get locally installed AUR packages
-    let output = Command::new("pacman").args(["-Qm"]).output();
-
-    if let Ok(output) = output {
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let local_packages: Vec<(String, String)> = stdout
-            .lines()
-            .filter_map(|line| {
-                let mut parts = line.split_whitespace();
-                let name = parts.next()?.to_string();
-                let version = parts.next()?.to_string();
-                Some((name, version))
-            })
-            .collect();
-
-        if local_packages.is_empty() {
-            // No AUR packages installed, reset cache
-            cache.0 = Some(now); // Update cache timestamp
-            cache.1 = 0; // No updates available
-            cache.2 = String::new(); // Clear output
-            return;
-        }
-
-        // Query AUR API for updates
-        let package_names: Vec<&str> = local_packages
-            .iter()
-            .map(|(name, _)| name.as_str())
-            .collect();
-
-        match query_aur_api(&package_names) {
-            Ok(aur_packages) => {
-                let mut updates = Vec::new();
-
-                for (local_name, local_version) in &local_packages {
-                    if let Some(aur_pkg) = aur_packages.iter().find(|p| p.name == *local_name) {
-                        // Only show update if AUR version is actually newer
-                        if is_version_newer(&aur_pkg.version, local_version) {
-                            updates.push(format!(
-                                "{} {} -> {}",
-                                local_name, local_version, aur_pkg.version
-                            ));
-                        }
-                    }
-                }
+    let output = Command::new("pacman")
+        .args(["-Qm"])
+        .output()
+        .map_err(|err| format!("failed to execute pacman: {}", err))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let local_packages: Vec<(String, String)> = stdout
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let name = parts.next()?.to_string();
+            let version = parts.next()?.to_string();
+            Some((name, version))
+        })
+        .collect();
 
-                let count = updates.len() as u16;
-                let stdout = updates.join("\n");
+    if local_packages.is_empty() {
+        // No AUR packages installed, reset cache
+        update_cache(|cache| {
+            cache.aur_synced = Some(now); // Update cache timestamp
+            cache.aur_updates = Vec::new(); // No updates available
+        });
+        return Ok(());
+    }
 
-                cache.0 = Some(now);
-                cache.1 = count;
-                cache.2 = stdout;
-            }
-            Err(_) => {
-                // AUR API failed (offline/error) - keep existing cache data but update timestamp
-                // to prevent repeated failed requests during this interval
-                cache.0 = Some(now);
+    // Query AUR API for updates
+    let package_names: Vec<&str> = local_packages
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+
+    match query_aur_api(&package_names) {
+        Ok(aur_packages) => {
+            let mut updates = Vec::new();
+
+            for (local_name, local_version) in &local_packages {
+                if let Some(aur_pkg) = aur_packages.iter().find(|p| p.name == *local_name) {
+                    // Only show update if AUR version is actually newer
+                    if is_version_newer(&aur_pkg.version, local_version) {
+                        let mut update =
+                            PackageUpdate::new(local_name, local_version, &aur_pkg.version);
+                        update.repository = Some("aur".to_string());
+                        update.build_date = Some(aur_pkg.last_modified);
+                        updates.push((aur_pkg.package_base.clone(), update));
+                    }
+                }
             }
+
+            update_cache(|cache| {
+                cache.aur_synced = Some(now);
+                cache.aur_updates = group_split_packages(updates);
+            });
+            Ok(())
+        }
+        Err(err) => {
+            // AUR API failed (offline/error) - keep existing cache data but update timestamp
+            // to prevent repeated failed requests during this interval
+            update_cache(|cache| cache.aur_synced = Some(now));
+            Err(format!("AUR query failed: {}", err))
         }
     }
 }
 
+// AUR updates as reported by paru or yay, which apply their own ignore lists
+// and devel tracking
+fn query_aur_helper(command: &[&str]) -> Result<Vec<PackageUpdate>, String> {
+    let output = Command::new(command[0])
+        .args(&command[1..])
+        .output()
+        .map_err(|err| format!("failed to execute {}: {}", command[0], err))?;
+    let mut updates = parse_updates(&String::from_utf8_lossy(&output.stdout));
+    // helpers exit with 1 and print nothing when there is nothing to upgrade, any
+    // other failure without output (a crash, a broken wrapper) is an error
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let nothing_to_upgrade = output.status.code() == Some(1) && stderr.trim().is_empty();
+    if !output.status.success() && updates.is_empty() && !nothing_to_upgrade {
+        return Err(match stderr.trim() {
+            "" => format!("{} failed with {}", command[0], output.status),
+            stderr => format!("{} failed: {}", command[0], stderr),
+        });
+    }
+    updates.retain(|update| !update.ignored);
+    // helpers list split packages individually, the local database knows their base
+    let handle = Alpm::new("/", DB_PATH.as_str()).ok();
+    let updates = updates
+        .into_iter()
+        .map(|mut update| {
+            update.repository = Some("aur".to_string());
+            let base = handle
+                .as_ref()
+                .and_then(|handle| handle.localdb().pkg(update.name.as_str()).ok())
+                .and_then(|pkg| pkg.base().map(|base| base.to_string()))
+                .unwrap_or_else(|| update.name.clone());
+            (base, update)
+        })
+        .collect();
+    Ok(group_split_packages(updates))
+}
+
 fn query_aur_api(package_names: &[&str]) -> Result<Vec<AurPackage>, Box<dyn std::error::Error>> {
     if package_names.is_empty() {
         return Ok(Vec::new());
@@ -297,32 +1618,116 @@ fn query_aur_api(package_names: &[&str]) -> Result<Vec<AurPackage>, Box<dyn std:
         url.push_str(&format!("&arg[]={}", name));
     }
 
-    let response: AurResponse = ureq::get(&url).call()?.into_json()?;
+    let response: AurResponse = HTTP.get(&url).call()?.into_json()?;
     Ok(response.results)
 }
 
-// get AUR updates from cache
-fn get_aur_updates() -> (u16, String) {
-    // Lock AUR cache to read: (last_update_time, update_count, formatted_output)
-    let cache = AUR_CACHE.lock().unwrap();
-    (cache.1, cache.2.clone())
+// get AUR updates from the shared cache, or the last known ones while it is being written
+fn get_aur_updates() -> Vec<PackageUpdate> {
+    let mut last = LAST_AUR_UPDATES.lock().unwrap();
+    if let Ok((_, cache)) = lock_cache(libc::LOCK_SH | libc::LOCK_NB) {
+        *last = cache.aur_updates;
+    }
+    // the list is from the last sync, leave out what was upgraded since
+    if !last.is_empty() {
+        if let Ok(handle) = Alpm::new("/", DB_PATH.as_str()) {
+            drop_installed(&mut last, |name| {
+                let pkg = handle.localdb().pkg(name).ok()?;
+                Some(pkg.version().as_str().to_string())
+            });
+        }
+    }
+    last.clone()
 }
 
 // get updates info without network operations
-fn get_updates() -> (u16, String) {
+fn get_updates() -> Result<Vec<PackageUpdate>, String> {
     // checkupdates --nosync --nocolor
     let output = Command::new("checkupdates")
         .args(["--nosync", "--nocolor"])
         .output()
-        .expect("failed to execute process");
-    match output.status.code() {
-        Some(_code) => {
-            let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-            if stdout.is_empty() {
-                return (0, String::new());
-            }
-            ((stdout.split(" -> ").count() as u16) - 1, stdout)
+        .map_err(|err| format!("failed to execute checkupdates: {}", err))?;
+    let stdout = checkupdates_result(&output)?;
+    // ignored packages won't be upgraded by pacman, so they don't count
+    let mut updates = parse_updates(&stdout);
+    updates.retain(|update| !update.ignored);
+    Ok(updates)
+}
+
+// query pacman.conf through pacman-conf, which also resolves Include directives
+// (e.g. `--repo-list` gives the sync repositories in order)
+fn pacman_conf(args: &[&str]) -> Vec<String> {
+    Command::new("pacman-conf")
+        .args(args)
+        .output()
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(|line| line.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// database path used by checkupdates, which holds the freshly synced repositories
+fn checkupdates_db_path() -> String {
+    if let Ok(path) = env::var("CHECKUPDATES_DB") {
+        return path;
+    }
+    let tmpdir = env::var("TMPDIR")
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .unwrap_or_else(|| "/tmp".to_string());
+    format!("{}/checkup-db-{}/", tmpdir, current_uid())
+}
+
+fn current_uid() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+// fill in repository, sizes and build date of pending repo updates
+fn annotate_updates(updates: &mut [PackageUpdate], repositories: &[String], cache_dirs: &[String]) {
+    let Ok(handle) = Alpm::new("/".to_string(), checkupdates_db_path()) else {
+        return;
+    };
+    for repository in repositories {
+        let _ = handle.register_syncdb(repository.as_str(), SigLevel::USE_DEFAULT);
+    }
+    for update in updates
+        .iter_mut()
+        .filter(|update| update.repository.is_none())
+    {
+        let found = handle
+            .syncdbs()
+            .into_iter()
+            .find_map(|db| db.pkg(update.name.as_str()).ok().map(|pkg| (db, pkg)));
+        if let Some((db, pkg)) = found {
+            // packages already in the cache don't need to be downloaded again
+            let cached = pkg.filename().is_some_and(|filename| {
+                cache_dirs
+                    .iter()
+                    .any(|dir| Path::new(dir).join(filename).exists())
+            });
+            update.repository = Some(db.name().to_string());
+            update.download_size = Some(if cached { 0 } else { pkg.size() });
+            update.installed_size_delta = handle
+                .localdb()
+                .pkg(update.name.as_str())
+                .ok()
+                .map(|local| pkg.isize() - local.isize());
+            update.build_date = Some(pkg.build_date());
+            // the security tracker lists split packages under their base
+            update.pkgbase = pkg.base().map(String::from);
         }
-        None => (0, String::new()),
     }
 }
+
+// A built-in palette name or the path of a GTK/waybar style sheet
+fn load_theme(source: &str) -> Theme {
+    if let Some(theme) = Theme::builtin(source) {
+        return theme;
+    }
+    let css = fs::read_to_string(source)
+        .unwrap_or_else(|err| panic!("Cannot read theme '{}': {}", source, err));
+    Theme::parse(&css).unwrap_or_else(|err| panic!("Invalid theme '{}': {}", source, err))
+}
@@ -0,0 +1,91 @@
+use assert_cmd::Command;
+use predicates::str::contains;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+// A scratch directory with stub `checkupdates` and `paru` scripts, so the module
+// runs without pacman or the network. paru reports nothing to upgrade.
+fn stub_environment(name: &str, checkupdates: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "waybar-module-pacman-updates-test-{}-{}",
+        std::process::id(),
+        name
+    ));
+    let bin = dir.join("bin");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&bin).unwrap();
+    for (stub, script) in [("checkupdates", checkupdates), ("paru", "exit 1")] {
+        let path = bin.join(stub);
+        fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    dir
+}
+
+fn module(dir: &Path, args: &[&str]) -> Command {
+    let path = format!(
+        "{}:{}",
+        dir.join("bin").display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let mut command = Command::cargo_bin("waybar-module-pacman-updates").unwrap();
+    command
+        .args(args)
+        .args(["--aur-backend", "paru"])
+        .env("PATH", path)
+        .env("XDG_CACHE_HOME", dir.join("cache"))
+        .env_remove("CHECKUPDATES_DB");
+    command
+}
+
+#[test]
+fn test_once_up_to_date() {
+    let dir = stub_environment("up-to-date", "exit 2");
+
+    module(&dir, &["--once"])
+        .assert()
+        .code(0)
+        .stdout("{\"alt\":\"updated\",\"class\":\"updated\",\"text\":\"0\",\"tooltip\":\"System updated\"}\n");
+    module(&dir, &["--list"]).assert().code(0).stdout("");
+    module(&dir, &["--once", "--no-aur"]).assert().code(0);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_once_with_updates() {
+    let dir = stub_environment("updates", "echo 'linux 6.9.1-1 -> 6.9.2-1'");
+
+    module(&dir, &["--once"])
+        .assert()
+        .code(2)
+        .stdout("{\"alt\":\"has-updates\",\"class\":\"has-updates\",\"text\":\"1\",\"tooltip\":\"linux 6.9.1-1 -> 6.9.2-1\"}\n");
+    module(&dir, &["--list"])
+        .assert()
+        .code(2)
+        .stdout("linux 6.9.1-1 -> 6.9.2-1\n");
+    module(&dir, &["--once", "--no-aur"])
+        .assert()
+        .code(2)
+        .stdout(contains("\"text\":\"1\""));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_once_checkupdates_failure() {
+    let dir = stub_environment(
+        "failure",
+        "echo '==> ERROR: Cannot fetch updates' >&2; exit 1",
+    );
+
+    for args in [&["--once"][..], &["--list"], &["--once", "--no-aur"]] {
+        module(&dir, args)
+            .assert()
+            .code(1)
+            .stdout("")
+            .stderr(contains(
+                "checkupdates failed: ==> ERROR: Cannot fetch updates",
+            ));
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
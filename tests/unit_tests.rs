@@ -1,4 +1,19 @@
-use waybar_module_pacman_updates::{highlight_semantic_version, is_version_newer, override_columns_from_packages};
+#[cfg(feature = "dbus")]
+use waybar_module_pacman_updates::UpdatesService;
+use waybar_module_pacman_updates::{
+    alpm_hook, app_dir, catches_signal, compare_url, decode_updates, details_cache_name,
+    display_width, drop_installed, encode_updates, fill_text_format, format_details,
+    format_duration, format_size, format_size_delta, gitlab_project, group_by_repository,
+    group_split_packages, highlight_semantic_version, highlight_version_change, history_events,
+    install_lags, is_devel_package, is_fresh, is_fresh_since, is_module_instance, is_version_newer,
+    new_updates, notification, override_columns_from_packages, pango_attributes, pango_to_polybar,
+    parse_advisories, parse_color, parse_commits, parse_history, parse_log_time, parse_pacman_log,
+    parse_updates, recorded_updates, security_fix, shell_quote, socket_path, sort_updates,
+    strip_markup, take_rebuilds, terminal_command, total_sizes, truncation_summary, update_kind,
+    upgrade_command, upgrade_script, version_tag, Alignment, AurBackend, Column, ColumnLayout,
+    HistoryEvent, ModuleState, Notifier, NotifierState, OutputFormat, PackageUpdate, RebuildMode,
+    Severity, SharedCache, SortKey, Theme, TooltipFormat, UpdateKind, Urgency,
+};
 
 #[test]
 fn test_version_comparison_semantic() {
@@ -30,7 +45,9 @@ fn test_version_comparison_mixed() {
 #[test]
 fn test_highlight_semantic_version_basic() {
     let input = "package 1.0.0 -> 1.1.0".to_string();
-    let colors = ["ff0000", "00ff00", "0000ff", "ff00ff", "ffffff"];
+    let colors = [
+        "ff0000", "00ff00", "0000ff", "ff00ff", "ffffff", "ff8800", "808080", "00ffff",
+    ];
     let overrides = ["", "", "", ""];
     let result = highlight_semantic_version(input, colors, false, overrides, None);
 
@@ -41,7 +58,9 @@ fn test_highlight_semantic_version_basic() {
 #[test]
 fn test_highlight_semantic_version_major() {
     let input = "package 1.0.0 -> 2.0.0".to_string();
-    let colors = ["ff0000", "00ff00", "0000ff", "ff00ff", "ffffff"];
+    let colors = [
+        "ff0000", "00ff00", "0000ff", "ff00ff", "ffffff", "ff8800", "808080", "00ffff",
+    ];
     let overrides = ["", "", "", ""];
     let result = highlight_semantic_version(input, colors, false, overrides, None);
 
@@ -51,7 +70,9 @@ fn test_highlight_semantic_version_major() {
 #[test]
 fn test_highlight_semantic_version_patch() {
     let input = "package 1.0.0 -> 1.0.1".to_string();
-    let colors = ["ff0000", "00ff00", "0000ff", "ff00ff", "ffffff"];
+    let colors = [
+        "ff0000", "00ff00", "0000ff", "ff00ff", "ffffff", "ff8800", "808080", "00ffff",
+    ];
     let overrides = ["", "", "", ""];
     let result = highlight_semantic_version(input, colors, false, overrides, None);
 
@@ -61,7 +82,9 @@ fn test_highlight_semantic_version_patch() {
 #[test]
 fn test_highlight_semantic_version_invalid_format() {
     let input = "invalid format".to_string();
-    let colors = ["ff0000", "00ff00", "0000ff", "ff00ff", "ffffff"];
+    let colors = [
+        "ff0000", "00ff00", "0000ff", "ff00ff", "ffffff", "ff8800", "808080", "00ffff",
+    ];
     let overrides = ["", "", "", ""];
     let result = highlight_semantic_version(input, colors, false, overrides, None);
 
@@ -72,8 +95,10 @@ fn test_highlight_semantic_version_invalid_format() {
 #[test]
 fn test_highlight_semantic_version_with_padding() {
     let input = "pkg 1.0.0 -> 1.1.0".to_string();
-    let colors = ["ff0000", "00ff00", "0000ff", "ff00ff", "ffffff"];
-    let padding = Some([10, 10, 10, 10]);
+    let colors = [
+        "ff0000", "00ff00", "0000ff", "ff00ff", "ffffff", "ff8800", "808080", "00ffff",
+    ];
+    let padding = Some(ColumnLayout::new([10, 10, 10, 10]));
     let input_len = input.len();
     let overrides = ["", "", "", ""];
     let result = highlight_semantic_version(input, colors, false, overrides, padding);
@@ -107,7 +132,7 @@ fn test_overwrite_columns_invalid_format() {
 fn test_overwrite_columns_with_padding() {
     let input = "pkg 1.0.0 -> 1.1.0".to_string();
     let overrides = ["808080", "dcdcdc", "d3d3d3", "c0c0c0"];
-    let padding = Some([10, 10, 10, 10]);
+    let padding = Some(ColumnLayout::new([10, 10, 10, 10]));
     let input_len = input.len();
     let result = override_columns_from_packages(input, overrides, padding);
 
@@ -116,4 +141,1085 @@ fn test_overwrite_columns_with_padding() {
     assert!(result.contains("span color='#d3d3d3'>->"));
     assert!(result.contains("span color='#c0c0c0'>1.1.0"));
     assert!(result.len() > input_len)
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_parse_updates_basic() {
+    let output = "linux 6.9.1.arch1-1 -> 6.9.2.arch1-1\nvim 9.1.0-1 -> 9.1.1-1\n";
+    let updates = parse_updates(output);
+
+    assert_eq!(
+        updates,
+        vec![
+            PackageUpdate::new("linux", "6.9.1.arch1-1", "6.9.2.arch1-1"),
+            PackageUpdate::new("vim", "9.1.0-1", "9.1.1-1"),
+        ]
+    );
+    assert_eq!(updates[1].to_string(), "vim 9.1.0-1 -> 9.1.1-1");
+}
+
+#[test]
+fn test_parse_updates_skips_warnings() {
+    let output = "warning: config file /etc/pacman.conf, line 12: directive 'Foo' not recognized\n\
+                  ==> ERROR: Cannot fetch updates\n\
+                  \n\
+                  glibc 2.39-1 -> 2.39-2\n";
+    let updates = parse_updates(output);
+
+    assert_eq!(
+        updates,
+        vec![PackageUpdate::new("glibc", "2.39-1", "2.39-2")]
+    );
+}
+
+#[test]
+fn test_parse_updates_extra_fields() {
+    let output = "firefox 126.0-1 -> 127.0-1 [ignored]\nmesa 1:24.1.0-1 -> 1:24.1.1-1 extra\n";
+    let updates = parse_updates(output);
+
+    assert_eq!(updates.len(), 2);
+    assert!(updates[0].ignored);
+    assert!(!updates[1].ignored);
+    assert_eq!(updates[1].old_version, "1:24.1.0-1");
+    assert_eq!(updates[1].new_version, "1:24.1.1-1");
+}
+
+#[test]
+fn test_group_by_repository() {
+    let mut updates = parse_updates(
+        "firefox 126.0-1 -> 127.0-1\nglibc 2.39-1 -> 2.39-2\nwine 9.9-1 -> 9.10-1\nlinux 6.9.1-1 -> 6.9.2-1\n",
+    );
+    for (update, repository) in updates
+        .iter_mut()
+        .zip(["extra", "core", "multilib", "core"])
+    {
+        update.repository = Some(repository.to_string());
+    }
+    let mut aur = PackageUpdate::new("yay", "12.3.0-1", "12.3.1-1");
+    aur.repository = Some("aur".to_string());
+    updates.push(aur);
+    updates.push(PackageUpdate::new("mystery", "1.0-1", "1.1-1"));
+
+    let repositories = ["core", "extra", "multilib"].map(String::from);
+    let groups = group_by_repository(&updates, &repositories);
+    let summary = groups
+        .iter()
+        .map(|(name, members)| (name.as_str(), members.len()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        summary,
+        vec![
+            ("core", 2),
+            ("extra", 1),
+            ("multilib", 1),
+            ("aur", 1),
+            ("unknown", 1)
+        ]
+    );
+    assert_eq!(groups[0].1[1].name, "linux");
+}
+
+#[test]
+fn test_update_kind() {
+    assert_eq!(update_kind("1.0.0", "2.0.0"), UpdateKind::Major);
+    assert_eq!(update_kind("1.0.0", "1.1.0"), UpdateKind::Minor);
+    assert_eq!(update_kind("1.0.0", "1.0.1"), UpdateKind::Patch);
+    assert_eq!(update_kind("1.0.1", "1.0.0"), UpdateKind::Other);
+}
+
+#[test]
+fn test_update_kind_pacman_versions() {
+    assert_eq!(update_kind("1:2.0-1", "2:1.0-1"), UpdateKind::Epoch);
+    assert_eq!(update_kind("1:2.0-1", "1:2.1-1"), UpdateKind::Minor);
+    assert_eq!(update_kind("1.2-1", "1.2-2"), UpdateKind::Rebuild);
+    assert_eq!(
+        update_kind("6.9.1.arch1-1", "6.9.2.arch1-1"),
+        UpdateKind::Patch
+    );
+    assert_eq!(
+        update_kind("6.9.arch1-1", "6.10.arch1-1"),
+        UpdateKind::Minor
+    );
+    assert_eq!(
+        update_kind("2.0.0rc1-1", "2.0.0rc2-1"),
+        UpdateKind::PreRelease
+    );
+    assert_eq!(update_kind("1.9-1", "2.0rc1-1"), UpdateKind::Major);
+    assert_eq!(
+        update_kind("r99.def4560-1", "r100.abc1234-1"),
+        UpdateKind::Devel
+    );
+    assert_eq!(
+        update_kind("0.47.0.r63.gccdddddd-2", "0.48.0.r62.gd775686-1"),
+        UpdateKind::Devel
+    );
+}
+
+#[test]
+fn test_update_kind_not_devel() {
+    // release candidates and short suffixes are not VCS revisions
+    assert_eq!(update_kind("1.0.r1-1", "1.0.r2-1"), UpdateKind::Patch);
+    assert_eq!(
+        update_kind("2.0.r5.abc-1", "2.0.r6.def-1"),
+        UpdateKind::Patch
+    );
+    assert_eq!(update_kind("1.2.git1-1", "1.3.git1-1"), UpdateKind::Minor);
+    assert_eq!(
+        update_kind("1.0.g1234567-1", "1.1.g1234567-1"),
+        UpdateKind::Minor
+    );
+    assert_eq!(update_kind("5.1-1", "5.2-1"), UpdateKind::Minor);
+}
+
+#[test]
+fn test_devel_package_names() {
+    assert!(is_devel_package("neovim-git"));
+    assert!(is_devel_package("foo-hg"));
+    assert!(!is_devel_package("git"));
+    assert!(!is_devel_package("gitg"));
+    assert!(!is_devel_package("python-gitpython"));
+    let update = PackageUpdate::new("neovim-git", "0.10.0-1", "0.11.0-1");
+    assert_eq!(update.kind(), UpdateKind::Devel);
+    let update = PackageUpdate::new("git", "2.45.0-1", "2.46.0-1");
+    assert_eq!(update.kind(), UpdateKind::Minor);
+}
+
+#[test]
+fn test_highlight_rebuild_color() {
+    let input = "package 1.2-1 -> 1.2-2".to_string();
+    let colors = [
+        "ff0000", "00ff00", "0000ff", "ff00ff", "ffffff", "ff8800", "808080", "00ffff",
+    ];
+    let overrides = ["", "", "", ""];
+    let result = highlight_semantic_version(input, colors, false, overrides, None);
+
+    assert!(result.contains("span color='#808080'"));
+}
+
+#[test]
+fn test_sort_updates() {
+    let mut updates = parse_updates(
+        "zlib 1.3-1 -> 1.3.1-1\nmesa 24.0.0-1 -> 25.0.0-1\nbash 5.2.0-1 -> 5.3.0-1\nyay 12.3.0-1 -> 12.3.1-1\n",
+    );
+    let sizes = [Some(100), Some(9000), Some(500), None];
+    let repositories = ["core", "extra", "aur"];
+    for (index, update) in updates.iter_mut().enumerate() {
+        update.download_size = sizes[index];
+        update.build_date = sizes[index].map(|size| size * 10);
+        update.repository = Some(repositories[index % 3].to_string());
+    }
+    let names = |updates: &[PackageUpdate]| {
+        updates
+            .iter()
+            .map(|update| update.name.clone())
+            .collect::<Vec<_>>()
+    };
+    let repositories = ["core", "extra"].map(String::from);
+
+    sort_updates(&mut updates, SortKey::Name, &repositories);
+    assert_eq!(names(&updates), ["bash", "mesa", "yay", "zlib"]);
+
+    sort_updates(&mut updates, SortKey::Kind, &repositories);
+    assert_eq!(names(&updates), ["mesa", "bash", "yay", "zlib"]);
+
+    sort_updates(&mut updates, SortKey::Size, &repositories);
+    assert_eq!(names(&updates), ["mesa", "bash", "zlib", "yay"]);
+
+    sort_updates(&mut updates, SortKey::Date, &repositories);
+    assert_eq!(names(&updates), ["mesa", "bash", "zlib", "yay"]);
+
+    sort_updates(&mut updates, SortKey::Repository, &repositories);
+    assert_eq!(names(&updates), ["yay", "zlib", "mesa", "bash"]);
+
+    assert_eq!("repo".parse::<SortKey>(), Ok(SortKey::Repository));
+    assert!("size-desc".parse::<SortKey>().is_err());
+}
+
+#[test]
+fn test_truncation_summary() {
+    let hidden = parse_updates(
+        "mesa 24.0.0-1 -> 25.0.0-1\nbash 5.2.0-1 -> 5.3.0-1\nqt6-base 6.7.0-1 -> 7.0.0-1\n",
+    );
+    assert_eq!(truncation_summary(&hidden), "…and 3 more (2 major)");
+    assert_eq!(truncation_summary(&hidden[1..2]), "…and 1 more");
+}
+
+#[test]
+fn test_size_totals_and_format() {
+    let mut updates = parse_updates("linux 6.9.1-1 -> 6.9.2-1\nvim 9.1.0-1 -> 9.1.1-1\n");
+    updates[0].download_size = Some(140 * 1024 * 1024);
+    updates[0].installed_size_delta = Some(2 * 1024 * 1024);
+    updates[1].download_size = Some(0); // already cached
+    updates[1].installed_size_delta = Some(-3 * 1024 * 1024);
+    updates.push(PackageUpdate::new("yay", "12.3.0-1", "12.3.1-1"));
+
+    let sizes = total_sizes(&updates);
+    assert_eq!(sizes, (140 * 1024 * 1024, -1024 * 1024));
+    assert_eq!(format_size(sizes.0), "140.00 MiB");
+    assert_eq!(format_size_delta(sizes.1), "-1.00 MiB");
+    assert_eq!(format_size_delta(1536), "+1.50 KiB");
+    assert_eq!(format_size(512), "512 B");
+    assert_eq!(
+        fill_text_format("{count} ({download_size}, {installed_delta})", 3, sizes),
+        "3 (140.00 MiB, -1.00 MiB)"
+    );
+}
+
+#[test]
+fn test_take_rebuilds() {
+    let mut updates = parse_updates(
+        "glibc 2.39-1 -> 2.39-2\nvim 9.1.0-1 -> 9.1.1-1\npython 3.12.3-1 -> 3.12.3-2\n",
+    );
+    let rebuilds = take_rebuilds(&mut updates);
+
+    assert_eq!(rebuilds.len(), 2);
+    assert_eq!(rebuilds[1].name, "python");
+    assert_eq!(
+        updates,
+        vec![PackageUpdate::new("vim", "9.1.0-1", "9.1.1-1")]
+    );
+    assert_eq!("collapse".parse::<RebuildMode>(), Ok(RebuildMode::Collapse));
+    assert!("squash".parse::<RebuildMode>().is_err());
+}
+
+#[test]
+fn test_column_layout_unicode_width() {
+    let updates = parse_updates("pkg 1.0-1 -> 1.1-1\nlonger-name 12.0-1 -> 12.1-1\n");
+    let format = TooltipFormat {
+        arrow: "→",
+        ..Default::default()
+    };
+    let rows = updates
+        .iter()
+        .map(|update| format.cells(update))
+        .collect::<Vec<_>>();
+    let layout = ColumnLayout::fit(
+        &rows,
+        &[
+            Alignment::Left,
+            Alignment::Left,
+            Alignment::Left,
+            Alignment::Right,
+        ],
+    );
+
+    assert_eq!(display_width("→"), 1);
+    assert_eq!(layout.widths, [11, 6, 1, 6]);
+    assert_eq!(layout.pad(0, "pkg"), "pkg        ");
+    assert_eq!(layout.pad(3, "1.1-1"), " 1.1-1");
+    // cells wider than the column are left as they are instead of panicking
+    assert_eq!(layout.pad(2, "=>"), "=>");
+}
+
+#[test]
+fn test_tooltip_format_extra_columns() {
+    let mut updates = parse_updates("vim 9.1.0-1 -> 9.1.1-1\nglibc 2.39-1 -> 2.39-2 [ignored]\n");
+    updates[0].repository = Some("extra".to_string());
+    updates[0].download_size = Some(2048);
+    let format = TooltipFormat {
+        arrow: "->",
+        extra_columns: vec!["repo".parse().unwrap(), Column::Size, Column::Kind],
+        kind_colors: None,
+        column_colors: Some(["", "", "", "00ff00"]),
+        ..Default::default()
+    };
+    let rows = updates
+        .iter()
+        .map(|update| format.cells(update))
+        .collect::<Vec<_>>();
+    let layout = ColumnLayout::fit(&rows, &[]);
+    let shown = updates.iter().collect::<Vec<_>>();
+
+    assert_eq!(
+        format.format_updates(&shown, Some(&layout)),
+        "vim   9.1.0-1 -> <span color='#00ff00'>9.1.1-1</span> extra 2.00 KiB patch  \n\
+         glibc 2.39-1  -> <span color='#00ff00'>2.39-2 </span> -     -        rebuild"
+    );
+    assert!("arch".parse::<Column>().is_err());
+}
+
+#[test]
+fn test_tooltip_format_kind_colors() {
+    let colors = [
+        "ff0000", "00ff00", "0000ff", "ff00ff", "ffffff", "ff8800", "808080", "00ffff",
+    ];
+    let format = TooltipFormat {
+        kind_colors: Some(colors),
+        ..Default::default()
+    };
+    let updates = parse_updates(
+        "package 1.0.0 -> 2.0.0\n\
+         package 1.0.0 -> 1.1.0\n\
+         package 1.0.0 -> 1.0.1\n\
+         package 1.2-1 -> 1.2-2",
+    );
+    let shown = updates.iter().collect::<Vec<_>>();
+
+    assert_eq!(
+        format.format_updates(&shown, None),
+        "<span color='#ff0000'>package 1.0.0 -> 2.0.0</span>\n\
+         <span color='#00ff00'>package 1.0.0 -> 1.1.0</span>\n\
+         <span color='#0000ff'>package 1.0.0 -> 1.0.1</span>\n\
+         <span color='#808080'>package 1.2-1 -> 1.2-2</span>"
+    );
+}
+
+#[test]
+fn test_tooltip_format_column_colors() {
+    let format = TooltipFormat {
+        column_colors: Some(["808080", "dcdcdc", "d3d3d3", "c0c0c0"]),
+        ..Default::default()
+    };
+    let update = PackageUpdate::new("pkg", "1.0.0", "1.1.0");
+
+    assert_eq!(
+        format.format_updates(&[&update], None),
+        "<span color='#808080'>pkg</span> <span color='#dcdcdc'>1.0.0</span> \
+         <span color='#d3d3d3'>-></span> <span color='#c0c0c0'>1.1.0</span>"
+    );
+    assert_eq!(
+        format.format_updates(&[&update], Some(&ColumnLayout::new([5, 6, 2, 6]))),
+        "<span color='#808080'>pkg  </span> <span color='#dcdcdc'>1.0.0 </span> \
+         <span color='#d3d3d3'>-></span> <span color='#c0c0c0'>1.1.0 </span>"
+    );
+}
+
+#[test]
+fn test_highlight_version_change() {
+    let bold = "weight='bold'";
+    assert_eq!(
+        highlight_version_change("1.4.6", "1.4.7", bold),
+        "1.4.<span weight='bold'>7</span>"
+    );
+    assert_eq!(
+        highlight_version_change("2.10-1", "2.11-1", bold),
+        "2.<span weight='bold'>11-1</span>"
+    );
+    assert_eq!(
+        highlight_version_change("1.2-1", "1.2-2", bold),
+        "1.2-<span weight='bold'>2</span>"
+    );
+    assert_eq!(
+        highlight_version_change("1.2", "1.2.1", bold),
+        "1.2.<span weight='bold'>1</span>"
+    );
+    assert_eq!(highlight_version_change("1.2", "1.2", bold), "1.2");
+    assert_eq!(
+        pango_attributes("bold,underline,color=ff0000"),
+        Ok("weight='bold' underline='single' color='#ff0000'".to_string())
+    );
+    assert!(pango_attributes("blink").is_err());
+}
+
+#[test]
+fn test_tooltip_format_version_change_padding() {
+    let updates = parse_updates("vim 9.1.0-1 -> 9.1.10-1\nglibc 2.39-1 -> 2.39-2\n");
+    let format = TooltipFormat {
+        version_change_attributes: Some("underline='single'".to_string()),
+        ..Default::default()
+    };
+    let rows = updates
+        .iter()
+        .map(|update| format.cells(update))
+        .collect::<Vec<_>>();
+    let layout = ColumnLayout::fit(&rows, &[]);
+    let shown = updates.iter().collect::<Vec<_>>();
+
+    assert_eq!(
+        format.format_updates(&shown, Some(&layout)),
+        "vim   9.1.0-1 -> 9.1.<span underline='single'>10-1</span>\n\
+         glibc 2.39-1  -> 2.39-<span underline='single'>2</span>  "
+    );
+}
+
+#[test]
+fn test_parse_color() {
+    assert_eq!(parse_color("#F80"), Ok("ff8800".to_string()));
+    assert_eq!(parse_color("ff000080"), Ok("ff000080".to_string()));
+    assert_eq!(parse_color("#fff8"), Ok("ffffff88".to_string()));
+    assert_eq!(parse_color("Orange"), Ok("ffa500".to_string()));
+    assert!(parse_color("#12345").is_err());
+    assert!(parse_color("reddish").is_err());
+}
+
+#[test]
+fn test_theme_parse() {
+    let theme = Theme::parse(
+        "/* palette */
+        @define-color accent #f00;
+        @define-color muted @accent;
+        .major, .epoch { color: @accent; }
+        .arrow { color: gray; }
+        .aur { font-style: italic; color: #00ff0080; }
+        .repo { font-style: normal; }",
+    )
+    .unwrap();
+
+    let kind_colors = theme.kind_colors.unwrap();
+    assert_eq!(kind_colors[UpdateKind::Major.color_slot()], "ff0000");
+    assert_eq!(kind_colors[UpdateKind::Epoch.color_slot()], "ff0000");
+    assert_eq!(kind_colors[UpdateKind::Minor.color_slot()], "00ff00");
+    assert_eq!(
+        theme.column_colors,
+        ["", "", "808080", ""].map(String::from)
+    );
+    assert_eq!(
+        theme.aur_style.as_deref(),
+        Some("style='italic' color='#00ff0080'")
+    );
+    assert_eq!(theme.repo_style.as_deref(), Some("style='normal'"));
+
+    assert!(Theme::parse(".major { color: @undefined; }").is_err());
+    assert!(Theme::parse(".major { font-weight: bold; }").is_err());
+    assert!(Theme::parse(".unknown { color: red; }").is_err());
+    assert!(Theme::builtin("nord").unwrap().kind_colors.is_some());
+    assert!(Theme::builtin("missing").is_none());
+}
+
+#[test]
+fn test_tooltip_format_source_styles() {
+    let mut updates = parse_updates("linux 6.9.1-1 -> 6.9.2-1\nparu 2.0.3-1 -> 2.0.4-1\n");
+    updates[1].repository = Some("aur".to_string());
+    let format = TooltipFormat {
+        aur_style: Some("style='italic'".to_string()),
+        ..Default::default()
+    };
+    let shown = updates.iter().collect::<Vec<_>>();
+
+    assert_eq!(
+        format.format_updates(&shown, None),
+        "linux 6.9.1-1 -> 6.9.2-1\n<span style='italic'>paru 2.0.3-1 -> 2.0.4-1</span>"
+    );
+}
+
+#[test]
+fn test_output_formats() {
+    let state = ModuleState {
+        text: "<span color='#ff0000'>2</span>".to_string(),
+        tooltip: "<b>a \"b\"</b>\nc".to_string(),
+        class: "has-updates",
+        security: false,
+        updates: parse_updates("linux 6.9.1-1 -> 6.9.2-1\nvim 9.1-1 -> 9.1-2\n"),
+    };
+
+    assert_eq!(
+        OutputFormat::Waybar.render(&state),
+        r#"{"alt":"has-updates","class":"has-updates","text":"<span color='#ff0000'>2</span>","tooltip":"<b>a \"b\"</b>\nc"}"#
+    );
+    assert_eq!(OutputFormat::Plain.render(&state), "2");
+    assert_eq!(OutputFormat::Polybar.render(&state), "%{F#ff0000}2%{F-}");
+    assert_eq!(
+        OutputFormat::Yambar.render(&state),
+        "count|int|2\ntext|string|2\nupdated|bool|false\n"
+    );
+    assert!(OutputFormat::I3bar.render(&state).ends_with("],"));
+
+    let json: serde_json::Value = serde_json::from_str(&OutputFormat::Json.render(&state)).unwrap();
+    assert_eq!(json["count"], 2);
+    assert_eq!(json["updates"][0]["name"], "linux");
+    assert_eq!(json["updates"][1]["kind"], "rebuild");
+    assert!("xmobar".parse::<OutputFormat>().is_err());
+}
+
+#[test]
+fn test_markup_translation() {
+    assert_eq!(
+        strip_markup("<span font-family='Fira Mono'>a &amp; <b>b</b></span>"),
+        "a & b"
+    );
+    assert_eq!(
+        pango_to_polybar(
+            "<span color='#ff0000'>a <span color='#00ff00' underline='single'>b</span> c</span> 100%"
+        ),
+        "%{F#ff0000}a %{F#00ff00}%{+u}b%{F#ff0000}%{-u} c%{F-} 100%%"
+    );
+}
+
+#[test]
+fn test_pacman_hook() {
+    let hook = alpm_hook("/usr/bin/waybar-module-pacman-updates");
+    assert!(hook.contains("When = PostTransaction\n"));
+    assert!(hook.contains("Exec = /usr/bin/waybar-module-pacman-updates notify\n"));
+
+    assert!(is_module_instance(
+        "/usr/bin/waybar-module-pacman-updates\0--no-zero-output\0"
+    ));
+    assert!(is_module_instance("waybar-module-pacman-updates\0"));
+    assert!(!is_module_instance(
+        "/usr/bin/waybar-module-pacman-updates\0notify\0"
+    ));
+    assert!(!is_module_instance(
+        "waybar-module-pacman-updates\0--sort\0name\0--once\0"
+    ));
+    assert!(!is_module_instance("vim\0waybar-module-pacman-updates\0"));
+    assert!(is_module_instance(
+        "waybar-module-pacman-updates\0daemon\0--no-aur\0"
+    ));
+    assert!(!is_module_instance(""));
+}
+
+#[test]
+fn test_catches_signal() {
+    // SIGUSR1 (10) and SIGUSR2 (12) handled
+    let status = "Name:\twaybar-module-p\nSigIgn:\t0000000000001000\nSigCgt:\t0000000000000a00\n";
+    assert!(catches_signal(status, 10));
+    assert!(catches_signal(status, 12));
+    assert!(!catches_signal(status, 13));
+    // still starting up, SIGUSR2 would kill it
+    assert!(!catches_signal("SigCgt:\t0000000000000000\n", 12));
+    assert!(!catches_signal("Name:\tfoo\n", 12));
+}
+
+#[test]
+fn test_daemon_protocol() {
+    let mut updates = parse_updates("linux 6.9.1-1 -> 6.9.2-1\n");
+    updates[0].repository = Some("core".to_string());
+    updates[0].download_size = Some(140 * 1024 * 1024);
+    let line = encode_updates(&updates);
+
+    assert!(!line.contains('\n'));
+    assert_eq!(decode_updates(&line), Ok(updates));
+    assert!(decode_updates("linux 6.9.1-1 -> 6.9.2-1").is_err());
+
+    assert_eq!(
+        socket_path(Some("/run/user/1000"), 1000),
+        std::path::PathBuf::from("/run/user/1000/waybar-module-pacman-updates.sock")
+    );
+    assert_eq!(
+        socket_path(None, 1000),
+        std::path::PathBuf::from("/tmp/waybar-module-pacman-updates-1000/daemon.sock")
+    );
+}
+
+#[test]
+fn test_shared_cache() {
+    let cache = SharedCache {
+        repo_synced: Some(1_700_000_000),
+        aur_synced: None,
+        aur_updates: parse_updates("paru 2.0.3-1 -> 2.0.4-1\n"),
+        ..Default::default()
+    };
+    assert_eq!(SharedCache::parse(&cache.to_json()), cache);
+    assert_eq!(SharedCache::parse(""), SharedCache::default());
+    assert_eq!(
+        SharedCache::parse("{\"repo_synced\":5}").repo_synced,
+        Some(5)
+    );
+
+    assert!(is_fresh(Some(100), 150, 60));
+    assert!(!is_fresh(Some(100), 160, 60));
+    assert!(!is_fresh(None, 150, 60));
+    assert!(!is_fresh(Some(100), 100, 0));
+    // a refresh asked for at 120 only accepts syncs from then on
+    assert!(is_fresh_since(Some(100), 150, 60, 0));
+    assert!(!is_fresh_since(Some(100), 150, 60, 120));
+    assert!(is_fresh_since(Some(120), 150, 60, 120));
+    assert!(!is_fresh_since(Some(120), 190, 60, 120));
+
+    assert_eq!(
+        app_dir(Some("/xdg"), Some("/home/me"), ".cache", 1000),
+        std::path::PathBuf::from("/xdg/waybar-module-pacman-updates")
+    );
+    assert_eq!(
+        app_dir(Some("relative"), Some("/home/me"), ".cache", 1000),
+        std::path::PathBuf::from("/home/me/.cache/waybar-module-pacman-updates")
+    );
+    assert_eq!(
+        app_dir(None, None, ".cache", 1000),
+        std::path::PathBuf::from("/tmp/waybar-module-pacman-updates-1000")
+    );
+}
+
+#[test]
+fn test_new_updates() {
+    let previous = parse_updates("vim 9.1-1 -> 9.1-2\nglibc 2.39-1 -> 2.39-2\n");
+    let current =
+        parse_updates("vim 9.1-1 -> 9.1-3\nglibc 2.39-1 -> 2.39-2\nlinux 6.9.1-1 -> 6.9.2-1\n");
+
+    let names = new_updates(&previous, &current)
+        .iter()
+        .map(|update| update.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["vim", "linux"]);
+}
+
+#[test]
+fn test_notifier_rate_limit() {
+    let repositories = vec!["core".to_string(), "extra".to_string()];
+    let notifier = Notifier::new(600, vec!["linux".to_string()], true);
+    let mut state = NotifierState::default();
+    let mut updates = parse_updates("vim 9.1-1 -> 9.1-2\n");
+    updates[0].repository = Some("extra".to_string());
+
+    // the first set is a baseline
+    assert_eq!(
+        notifier.update(&mut state, &updates, &repositories, 1000),
+        None
+    );
+
+    updates.extend(parse_updates("htop 3.3-1 -> 3.4-1\n"));
+    updates[1].repository = Some("extra".to_string());
+    let first = notifier
+        .update(&mut state, &updates, &repositories, 1010)
+        .unwrap();
+    assert_eq!(first.summary, "1 new update");
+    assert_eq!(first.body, "extra: htop");
+    assert_eq!(first.urgency, Urgency::Normal);
+
+    // rate limited, collected for later
+    updates.extend(parse_updates("paru 2.0.3-1 -> 2.0.4-1\n"));
+    updates[2].repository = Some("aur".to_string());
+    assert_eq!(
+        notifier.update(&mut state, &updates, &repositories, 1100),
+        None
+    );
+    updates.extend(parse_updates("tree 2.1-1 -> 2.2-1\n"));
+    updates[3].repository = Some("extra".to_string());
+    assert_eq!(
+        notifier.update(&mut state, &updates, &repositories, 1200),
+        None
+    );
+
+    // critical packages skip the rate limit and bring the collected ones along
+    updates.extend(parse_updates("linux 6.9.1-1 -> 6.9.2-1\n"));
+    updates[4].repository = Some("core".to_string());
+    let critical = notifier
+        .update(&mut state, &updates, &repositories, 1300)
+        .unwrap();
+    assert_eq!(critical.summary, "3 new updates");
+    assert_eq!(critical.body, "core: linux\nextra: tree\naur: paru");
+    assert_eq!(critical.urgency, Urgency::Critical);
+
+    assert_eq!(
+        notifier.update(&mut state, &updates, &repositories, 2000),
+        None
+    );
+}
+
+#[test]
+fn test_notifier_shared_state() {
+    let repositories = vec!["extra".to_string()];
+    let update_in = |repository: &str, line: &str| {
+        let mut update = parse_updates(line).remove(0);
+        update.repository = Some(repository.to_string());
+        update
+    };
+    // two bars, one of them with --no-aur, share the state through the cache
+    let with_aur = Notifier::new(0, Vec::new(), true);
+    let without_aur = Notifier::new(0, Vec::new(), false);
+    let mut state = NotifierState::default();
+    let mut aur = vec![update_in("aur", "paru 2.0.3-1 -> 2.0.4-1")];
+    let mut repo = vec![update_in("extra", "vim 9.1-1 -> 9.1-2")];
+    let both = |repo: &[PackageUpdate], aur: &[PackageUpdate]| [repo, aur].concat();
+
+    assert_eq!(
+        with_aur.update(&mut state, &both(&repo, &aur), &repositories, 10),
+        None
+    );
+    assert_eq!(
+        without_aur.update(&mut state, &repo, &repositories, 10),
+        None
+    );
+
+    repo.push(update_in("extra", "htop 3.3-1 -> 3.4-1"));
+    aur.push(update_in("aur", "yay 12.3-1 -> 12.4-1"));
+    let first = without_aur
+        .update(&mut state, &repo, &repositories, 20)
+        .unwrap();
+    assert_eq!(first.body, "extra: htop");
+    let second = with_aur
+        .update(&mut state, &both(&repo, &aur), &repositories, 30)
+        .unwrap();
+    assert_eq!(second.body, "aur: yay");
+    // nothing is notified twice, and the AUR updates aren't forgotten
+    assert_eq!(
+        without_aur.update(&mut state, &repo, &repositories, 40),
+        None
+    );
+    assert_eq!(
+        with_aur.update(&mut state, &both(&repo, &aur), &repositories, 50),
+        None
+    );
+
+    // an annotated copy of a collected update is still the same update
+    let limited = Notifier::new(600, Vec::new(), true);
+    repo.push(update_in("extra", "tree 2.1-1 -> 2.2-1"));
+    assert_eq!(limited.update(&mut state, &repo, &repositories, 60), None);
+    repo[2].download_size = Some(1024);
+    assert_eq!(limited.update(&mut state, &repo, &repositories, 70), None);
+    let collected = limited
+        .update(&mut state, &repo, &repositories, 700)
+        .unwrap();
+    assert_eq!(collected.body, "extra: tree");
+}
+
+#[cfg(feature = "dbus")]
+#[test]
+fn test_updates_service_properties() {
+    let mut service = UpdatesService::new(|| {});
+    let mut updates = parse_updates(
+        "linux 6.9.1-1 -> 6.9.2-1\nvim 9.1.0-1 -> 9.2.0-1\nparu 2.0.3-1 -> 2.0.3-2\n",
+    );
+    for (update, repository) in updates.iter_mut().zip(["core", "extra", "aur"]) {
+        update.repository = Some(repository.to_string());
+    }
+    assert_eq!(service.count(), 0);
+
+    assert!(service.set_updates(&updates));
+    assert!(!service.set_updates(&updates));
+    assert_eq!(service.count(), 3);
+    assert_eq!(service.repo_count(), 2);
+    assert_eq!(service.aur_count(), 1);
+    assert_eq!(
+        service.updates()[1],
+        (
+            "vim".to_string(),
+            "9.1.0-1".to_string(),
+            "9.2.0-1".to_string(),
+            "extra".to_string(),
+            "minor".to_string()
+        )
+    );
+    assert_eq!(service.updates()[2].4, "rebuild");
+
+    // updates without a known repository count as repository updates
+    assert!(service.set_updates(&[PackageUpdate::new("glibc", "2.39-1", "2.40-1")]));
+    assert_eq!((service.repo_count(), service.aur_count()), (1, 0));
+    assert_eq!(service.updates()[0].3, "");
+}
+
+#[test]
+fn test_upgrade_commands() {
+    let command = vec!["sh".to_string(), "-c".to_string(), "paru -Syu".to_string()];
+
+    assert_eq!(
+        terminal_command("foot", &command),
+        ["foot", "sh", "-c", "paru -Syu"]
+    );
+    assert_eq!(
+        terminal_command("/usr/bin/wezterm", &command),
+        ["/usr/bin/wezterm", "start", "--", "sh", "-c", "paru -Syu"]
+    );
+    assert_eq!(
+        terminal_command("alacritty --class updates", &command),
+        [
+            "alacritty",
+            "--class",
+            "updates",
+            "-e",
+            "sh",
+            "-c",
+            "paru -Syu"
+        ]
+    );
+    assert_eq!(
+        terminal_command("gnome-terminal", &command),
+        ["gnome-terminal", "--", "sh", "-c", "paru -Syu"]
+    );
+
+    assert_eq!(upgrade_command(Some("yay")), ["yay", "-Syu"]);
+    assert_eq!(upgrade_command(None), ["sudo", "pacman", "-Syu"]);
+}
+
+#[test]
+fn test_upgrade_script() {
+    assert_eq!(shell_quote("-Syu"), "-Syu");
+    assert_eq!(shell_quote("/usr/bin/paru"), "/usr/bin/paru");
+    assert_eq!(shell_quote("my helper"), "'my helper'");
+    assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    assert_eq!(shell_quote(""), "''");
+    assert_eq!(shell_quote("$(rm -rf ~)"), "'$(rm -rf ~)'");
+
+    assert_eq!(
+        upgrade_script(
+            &upgrade_command(Some("paru")),
+            "/home/me/my bin/waybar-module-pacman-updates"
+        ),
+        "paru -Syu; '/home/me/my bin/waybar-module-pacman-updates' notify; \
+         echo; printf 'Press Enter to close'; read -r _"
+    );
+}
+
+#[test]
+fn test_aur_backend() {
+    assert_eq!("api".parse(), Ok(AurBackend::Api));
+    assert_eq!("paru".parse(), Ok(AurBackend::Paru));
+    assert!("pikaur".parse::<AurBackend>().is_err());
+
+    assert_eq!(AurBackend::Api.helper_command(), None);
+    assert_eq!(
+        AurBackend::Yay.helper_command(),
+        Some(["yay", "-Qua", "--devel", "--color", "never"])
+    );
+
+    // paru/yay print updates the way checkupdates does
+    let updates = parse_updates("paru-bin 2.0.3-1 -> 2.0.4-1\nfoo-git r10.abc-1 -> r12.def-1\n");
+    assert_eq!(updates.len(), 2);
+    assert_eq!(updates[1].name, "foo-git");
+    assert_eq!(updates[1].new_version, "r12.def-1");
+}
+
+#[test]
+fn test_group_split_packages() {
+    let updates = vec![
+        (
+            "foo".to_string(),
+            PackageUpdate::new("foo-libs", "1.0-1", "1.1-1"),
+        ),
+        (
+            "bar".to_string(),
+            PackageUpdate::new("bar", "2.0-1", "2.1-1"),
+        ),
+        (
+            "foo".to_string(),
+            PackageUpdate::new("foo", "1.0-1", "1.1-1"),
+        ),
+        (
+            "baz".to_string(),
+            PackageUpdate::new("baz-cli", "3.0-1", "3.1-1"),
+        ),
+        // out of sync with its base, e.g. only one member was rebuilt locally
+        (
+            "foo".to_string(),
+            PackageUpdate::new("foo-docs", "0.9-1", "1.1-1"),
+        ),
+    ];
+    let grouped = group_split_packages(updates);
+
+    assert_eq!(grouped.len(), 4);
+    // the name stays a real package, for critical packages and the history
+    assert_eq!(grouped[0].name, "foo");
+    assert_eq!(grouped[0].base.as_deref(), Some("foo"));
+    assert_eq!(grouped[0].split_packages, ["foo", "foo-libs"]);
+    assert_eq!(grouped[0].packages(), ["foo", "foo-libs"]);
+    assert_eq!(grouped[0].display_name(), "foo (foo, foo-libs)");
+    assert_eq!(grouped[1].display_name(), "bar");
+    assert_eq!(grouped[1].base, None);
+    assert_eq!(grouped[1].packages(), ["bar"]);
+    assert_eq!(grouped[2].name, "baz-cli");
+    assert_eq!(grouped[2].display_name(), "baz (baz-cli)");
+    assert_eq!(grouped[3].name, "foo-docs");
+    assert_eq!(grouped[3].display_name(), "foo (foo-docs)");
+    // every member of a split package counts as critical
+    let critical = notification(&grouped[..1], &[], &["foo-libs".to_string()]);
+    assert_eq!(critical.urgency, Urgency::Critical);
+}
+
+#[test]
+fn test_drop_installed() {
+    let mut updates = parse_updates(
+        "paru 2.0.3-1 -> 2.0.4-1\nyay 12.3-1 -> 12.4-1\nfoo 1.0-1 -> 1.1-1\ngone 1.0-1 -> 1.1-1\n",
+    );
+    updates[2].split_packages = vec!["foo".to_string(), "foo-libs".to_string()];
+    let installed = |name: &str| match name {
+        // upgraded since the sync
+        "paru" => Some("2.0.4-1".to_string()),
+        "yay" => Some("12.3-1".to_string()),
+        // only one member of the split package was upgraded
+        "foo" => Some("1.1-1".to_string()),
+        "foo-libs" => Some("1.0-1".to_string()),
+        _ => None,
+    };
+    drop_installed(&mut updates, installed);
+
+    let names = updates
+        .iter()
+        .map(|update| update.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["yay", "foo"]);
+}
+
+#[test]
+fn test_security_fix() {
+    let advisories = parse_advisories(
+        r#"[
+            {"name": "AVG-1", "packages": ["openssl", "lib32-openssl"], "status": "Fixed",
+             "severity": "Medium", "type": "denial of service", "affected": "3.0.7-1",
+             "fixed": "3.0.8-1", "ticket": null, "issues": ["CVE-2023-0001"], "advisories": []},
+            {"name": "AVG-2", "packages": ["openssl"], "status": "Testing",
+             "severity": "High", "type": "arbitrary code execution", "affected": "3.0.0-1",
+             "fixed": "3.0.9-1", "ticket": null, "issues": ["CVE-2023-0002", "CVE-2023-0003"],
+             "advisories": []},
+            {"name": "AVG-3", "packages": ["openssl"], "status": "Vulnerable",
+             "severity": "Critical", "type": "unknown", "affected": "3.0.0-1",
+             "fixed": null, "ticket": null, "issues": ["CVE-2023-0004"], "advisories": []}
+        ]"#,
+    )
+    .unwrap();
+    // the advisory without a fix is dropped
+    assert_eq!(advisories.len(), 2);
+
+    let update = PackageUpdate::new("openssl", "3.0.7-1", "3.0.9-1");
+    let fix = security_fix(&update, &advisories).unwrap();
+    assert_eq!(fix.severity, Severity::High);
+    assert_eq!(fix.to_string(), "High: CVE-2023-0001 +2");
+
+    // the new version only fixes AVG-1
+    let update = PackageUpdate::new("openssl", "3.0.7-1", "3.0.8-1");
+    assert_eq!(
+        security_fix(&update, &advisories).unwrap().issues,
+        ["CVE-2023-0001"]
+    );
+    // installed before the affected version
+    let update = PackageUpdate::new("lib32-openssl", "3.0.6-1", "3.0.8-1");
+    assert_eq!(security_fix(&update, &advisories), None);
+    let update = PackageUpdate::new("linux", "6.9.1-1", "6.9.2-1");
+    assert_eq!(security_fix(&update, &advisories), None);
+    // split packages are tracked under their base
+    let mut update = PackageUpdate::new("openssl-docs", "3.0.7-1", "3.0.8-1");
+    assert_eq!(security_fix(&update, &advisories), None);
+    update.pkgbase = Some("openssl".to_string());
+    assert_eq!(
+        security_fix(&update, &advisories).unwrap().issues,
+        ["CVE-2023-0001"]
+    );
+    // the base of a repo package isn't shown, unlike that of grouped AUR packages
+    assert_eq!(update.display_name(), "openssl-docs");
+
+    let mut updates = parse_updates("openssl 3.0.7-1 -> 3.0.9-1\n");
+    updates[0].security = security_fix(&updates[0], &advisories);
+    let state = ModuleState {
+        text: "1".to_string(),
+        tooltip: TooltipFormat::default().format_updates(&[&updates[0]], None),
+        class: "has-updates",
+        security: true,
+        updates,
+    };
+    assert_eq!(
+        state.tooltip,
+        "openssl 3.0.7-1 -> 3.0.9-1 <b>[High: CVE-2023-0001 +2]</b>"
+    );
+    assert_eq!(
+        OutputFormat::Waybar.render(&state),
+        r#"{"alt":"has-updates","class":["has-updates","security"],"text":"1","tooltip":"openssl 3.0.7-1 -> 3.0.9-1 <b>[High: CVE-2023-0001 +2]</b>"}"#
+    );
+}
+
+#[test]
+fn test_gitlab_project_names() {
+    let project = |pkgbase| {
+        gitlab_project(pkgbase)
+            .trim_start_matches("archlinux/packaging/packages/")
+            .to_string()
+    };
+    assert_eq!(project("linux"), "linux");
+    assert_eq!(project("dvd+rw-tools"), "dvd-rw-tools");
+    assert_eq!(project("libsigc++"), "libsigcplusplus");
+    assert_eq!(project("gtk+"), "gtkplus");
+    assert_eq!(project("tree"), "unix-tree");
+    assert_eq!(project("tree-sitter"), "tree-sitter");
+    assert_eq!(project("python-zope.interface"), "python-zope.interface");
+    assert_eq!(project("perl-foo__bar"), "perl-foo-bar");
+    assert_eq!(project("foo_bar"), "foo_bar");
+    assert_eq!(project("a+b+c"), "a-bplusc");
+}
+
+#[test]
+fn test_packaging_details() {
+    let update = PackageUpdate::new("libsigc++", "1:3.6.0-1", "1:3.6.0+r2-1");
+
+    assert_eq!(
+        gitlab_project("libsigc++"),
+        "archlinux/packaging/packages/libsigcplusplus"
+    );
+    assert_eq!(version_tag("1:3.6.0-1"), "1-3.6.0-1");
+    assert_eq!(
+        details_cache_name("https://gitlab.example.org:8443/", "libsigc++", &update),
+        "gitlab.example.org_8443_archlinux_packaging_packages_libsigcplusplus_1-3.6.0-1_1-3.6.0+r2-1.json"
+    );
+    assert_eq!(
+        compare_url("https://gitlab.example.org/", "libsigc++", &update),
+        "https://gitlab.example.org/api/v4/projects/archlinux%2Fpackaging%2Fpackages%2Flibsigcplusplus/repository/compare?from=1-3.6.0-1&to=1-3.6.0%2Br2-1"
+    );
+
+    let commits = parse_commits(
+        r#"{"commits": [
+            {"id": "a1", "short_id": "a1b2c3d4", "title": "upgpkg: 1:3.6.0-2", "author_name": "Jane",
+             "created_at": "2024-05-01T10:00:00.000+00:00"},
+            {"id": "e5", "short_id": "e5f6a7b8", "title": "upgpkg: 1:3.6.0+r2-1", "author_name": "Jane",
+             "created_at": "2024-06-02T10:00:00.000+00:00"}
+        ], "diffs": []}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        format_details(
+            &update,
+            Some("https://libsigcplusplus.github.io"),
+            "page",
+            &commits
+        ),
+        "libsigc++ 1:3.6.0-1 -> 1:3.6.0+r2-1\n\
+         Upstream: https://libsigcplusplus.github.io\n\
+         Packaging: page\n  \
+         e5f6a7b8 upgpkg: 1:3.6.0+r2-1 (Jane, 2024-06-02)\n  \
+         a1b2c3d4 upgpkg: 1:3.6.0-2 (Jane, 2024-05-01)"
+    );
+    assert!(parse_commits("{}").is_err());
+}
+
+#[test]
+fn test_history() {
+    let previous = parse_updates("linux 6.9.1-1 -> 6.9.2-1\nvim 9.1-1 -> 9.1-2\n");
+    let current = parse_updates("linux 6.9.1-1 -> 6.9.3-1\nmesa 24.1-1 -> 24.2-1\n");
+    let events = history_events(&previous, &current, 1000);
+
+    let summary = events
+        .iter()
+        .map(|entry| (entry.event, entry.name.as_str(), entry.new_version.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        [
+            (HistoryEvent::Available, "linux", "6.9.3-1"),
+            (HistoryEvent::Available, "mesa", "24.2-1"),
+            (HistoryEvent::Applied, "vim", "9.1-2"),
+        ]
+    );
+    assert!(history_events(&current, &current, 1000).is_empty());
+
+    // an instance with --no-aur leaves the recorded AUR updates alone
+    let mut recorded = current.clone();
+    recorded.extend(parse_updates("paru 2.0.3-1 -> 2.0.4-1\n"));
+    recorded[2].repository = Some("aur".to_string());
+    let without_aur = recorded_updates(&recorded, &current, false);
+    assert_eq!(without_aur, recorded);
+    assert!(history_events(&recorded, &without_aur, 1000).is_empty());
+    let with_aur = recorded_updates(&recorded, &current, true);
+    assert_eq!(history_events(&recorded, &with_aur, 1000)[0].name, "paru");
+
+    let jsonl = events
+        .iter()
+        .map(|entry| serde_json::to_string(entry).unwrap())
+        .collect::<Vec<_>>()
+        .join("\n");
+    assert!(jsonl.starts_with(r#"{"time":1000,"event":"available","name":"linux""#));
+    assert_eq!(parse_history(&format!("{}\nnot json\n", jsonl)), events);
+
+    assert_eq!(parse_log_time("1970-01-01T01:00:00+0100"), Some(0));
+    assert_eq!(parse_log_time("2024-02-29T12:00:00-0030"), Some(1709209800));
+    assert_eq!(parse_log_time("2019-01-01 10:00"), None);
+
+    let log = "[2024-05-30T10:00:00+0000] [PACMAN] starting full system upgrade\n\
+               [1970-01-01T00:20:00+0000] [ALPM] upgraded mesa (24.1-1 -> 24.2-1)\n\
+               [1970-01-01T00:30:00+0000] [ALPM] upgraded linux (6.9.1-1 -> 6.9.3-1)\n\
+               [1970-01-01T00:30:00+0000] [ALPM] installed foo (1.0-1)\n";
+    let upgrades = parse_pacman_log(log);
+    assert_eq!(upgrades.len(), 2);
+    assert_eq!(upgrades[1].name, "linux");
+    assert_eq!(upgrades[1].old_version, "6.9.1-1");
+    assert_eq!(upgrades[1].timestamp, "1970-01-01T00:30:00+0000");
+
+    let lags = install_lags(&events, &upgrades);
+    assert_eq!(
+        lags.iter()
+            .map(|(entry, _, lag)| (entry.name.as_str(), *lag))
+            .collect::<Vec<_>>(),
+        [("linux", 800), ("mesa", 200)]
+    );
+
+    assert_eq!(format_duration(45), "45s");
+    assert_eq!(format_duration(800), "13m");
+    assert_eq!(format_duration(3 * 3600 + 120), "3h 2m");
+    assert_eq!(format_duration(2 * 86400 + 4 * 3600), "2d 4h");
+}